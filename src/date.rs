@@ -0,0 +1,106 @@
+// src/date.rs
+
+//! A minimal calendar date (UTC), so we don't need a date/time dependency.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A calendar date in the proleptic Gregorian calendar.
+///
+/// Field order makes the derived `Ord` chronological.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32, // 1-12
+    pub day: u32,   // 1-31
+}
+
+impl Date {
+    /// The UTC date a point in time falls on
+    pub fn from_system_time(time: SystemTime) -> Date {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        };
+
+        Date::from_days(secs.div_euclid(86_400))
+    }
+
+    /// Today's date in UTC
+    pub fn today() -> Date {
+        Date::from_system_time(SystemTime::now())
+    }
+
+    /// Build a date, checking that the day exists in that month
+    pub fn new(year: i32, month: u32, day: u32) -> Result<Date, String> {
+        if !(1..=12).contains(&month) {
+            return Err(format!("month must be 1-12, got {}", month));
+        }
+
+        let days_in_month = match month {
+            2 if is_leap_year(year) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+        if !(1..=days_in_month).contains(&day) {
+            return Err(format!(
+                "day must be 1-{} for {:04}-{:02}, got {}",
+                days_in_month, year, month, day
+            ));
+        }
+
+        Ok(Date { year, month, day })
+    }
+
+    /// Convert days since 1970-01-01 to a date (Howard Hinnant's algorithm)
+    fn from_days(days: i64) -> Date {
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097); // [0, 146096]
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365; // [0, 399]
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100); // [0, 365]
+        let mp = (5 * doy + 2) / 153; // [0, 11], March-based
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as i32;
+
+        Date { year, month, day }
+    }
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Parses `YYYY-MM-DD`, rejecting dates that don't exist (e.g. `2023-02-29`)
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Date, String> {
+        let invalid = || format!("invalid date '{}' (expected YYYY-MM-DD)", s);
+
+        let mut parts = s.trim().split('-');
+        let (Some(year), Some(month), Some(day), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+            return Err(invalid());
+        }
+
+        let year = year.parse().map_err(|_| invalid())?;
+        let month = month.parse().map_err(|_| invalid())?;
+        let day = day.parse().map_err(|_| invalid())?;
+        Date::new(year, month, day)
+    }
+}
+
+/// Formats as `YYYY-MM-DD`
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
@@ -0,0 +1,415 @@
+// src/json.rs
+
+//! Just enough JSON to persist a tree: a value type, a writer and a parser.
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>), // Keeps insertion order for stable output
+}
+
+/// Drop nested arrays and objects iteratively, so a document as deep as a
+/// long chain of tree nodes can't overflow the stack.
+impl Drop for Value {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_children(&mut stack);
+        while let Some(mut value) = stack.pop() {
+            // Emptied first, so dropping `value` itself is shallow
+            value.take_children(&mut stack);
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Value {
+    /// Look up a key in an object. Returns `None` for non-objects.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// The number as a `u64`, if it is a non-negative integer
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 && *n <= u64::MAX as f64 => {
+                Some(*n as u64)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Move the items of an array or the values of an object onto `stack`
+    fn take_children(&mut self, stack: &mut Vec<Value>) {
+        match self {
+            Value::Array(items) => stack.append(items),
+            Value::Object(fields) => stack.extend(fields.drain(..).map(|(_, value)| value)),
+            _ => {}
+        }
+    }
+
+    /// Serialize to a string. `pretty` uses 2-space indentation.
+    pub fn to_json(&self, pretty: bool) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out, pretty, 0);
+        out
+    }
+
+    fn write_json(&self, out: &mut String, pretty: bool, level: usize) {
+        // Explicit stack of pending output, so deep documents can't overflow
+        // the call stack. Pushed in reverse, since the last push runs first.
+        let mut stack = vec![Pending::Value(self, level)];
+        while let Some(pending) = stack.pop() {
+            let (value, level) = match pending {
+                Pending::Text(text) => {
+                    out.push_str(text);
+                    continue;
+                }
+                Pending::Newline(level) => {
+                    newline(out, pretty, level);
+                    continue;
+                }
+                Pending::Key(key) => {
+                    out.push_str(&quote(key));
+                    out.push(':');
+                    if pretty {
+                        out.push(' ');
+                    }
+                    continue;
+                }
+                Pending::Value(value, level) => (value, level),
+            };
+
+            match value {
+                Value::Null => out.push_str("null"),
+                Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+                Value::Number(n) => {
+                    let _ = write!(out, "{}", n);
+                }
+                Value::String(s) => out.push_str(&quote(s)),
+                Value::Array(items) if items.is_empty() => out.push_str("[]"),
+                Value::Array(items) => {
+                    out.push('[');
+                    stack.push(Pending::Text("]"));
+                    stack.push(Pending::Newline(level));
+                    for (i, item) in items.iter().enumerate().rev() {
+                        stack.push(Pending::Value(item, level + 1));
+                        stack.push(Pending::Newline(level + 1));
+                        if i > 0 {
+                            stack.push(Pending::Text(","));
+                        }
+                    }
+                }
+                Value::Object(fields) if fields.is_empty() => out.push_str("{}"),
+                Value::Object(fields) => {
+                    out.push('{');
+                    stack.push(Pending::Text("}"));
+                    stack.push(Pending::Newline(level));
+                    for (i, (key, value)) in fields.iter().enumerate().rev() {
+                        stack.push(Pending::Value(value, level + 1));
+                        stack.push(Pending::Key(key));
+                        stack.push(Pending::Newline(level + 1));
+                        if i > 0 {
+                            stack.push(Pending::Text(","));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A piece of output still to be written by `Value::write_json`
+enum Pending<'a> {
+    Value(&'a Value, usize), // With its indent level
+    Key(&'a str),
+    Newline(usize),
+    Text(&'static str),
+}
+
+fn newline(out: &mut String, pretty: bool, level: usize) {
+    if pretty {
+        out.push('\n');
+        out.push_str(&"  ".repeat(level));
+    }
+}
+
+/// Quote and escape a string as a JSON string literal
+pub fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Parse a complete JSON document
+pub fn parse(input: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+    };
+
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(format!("unexpected trailing data at offset {}", parser.pos));
+    }
+
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at offset {}", c, self.pos))
+        }
+    }
+
+    /// Parse one value. Arrays and objects being filled are kept on an
+    /// explicit stack rather than the call stack, so nesting depth is only
+    /// limited by memory.
+    fn parse_value(&mut self) -> Result<Value, String> {
+        let mut open: Vec<Open> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let mut value = match self.peek() {
+                Some('{') => {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    if self.peek() == Some('}') {
+                        self.pos += 1;
+                        Value::Object(Vec::new())
+                    } else {
+                        let key = self.parse_key()?;
+                        open.push(Open::Object(Vec::new(), key));
+                        continue;
+                    }
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    if self.peek() == Some(']') {
+                        self.pos += 1;
+                        Value::Array(Vec::new())
+                    } else {
+                        open.push(Open::Array(Vec::new()));
+                        continue;
+                    }
+                }
+                Some('"') => Value::String(self.parse_string()?),
+                Some('t') => self.parse_literal("true", Value::Bool(true))?,
+                Some('f') => self.parse_literal("false", Value::Bool(false))?,
+                Some('n') => self.parse_literal("null", Value::Null)?,
+                Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number()?,
+                Some(c) => return Err(format!("unexpected '{}' at offset {}", c, self.pos)),
+                None => return Err("unexpected end of input".into()),
+            };
+
+            // Hand the finished value to its container, closing containers
+            // for as long as they end right after it
+            loop {
+                self.skip_whitespace();
+                let closed = match open.last_mut() {
+                    None => return Ok(value),
+                    Some(Open::Array(items)) => {
+                        items.push(value);
+                        match self.peek() {
+                            Some(',') => false,
+                            Some(']') => true,
+                            _ => return Err(format!("expected ',' or ']' at offset {}", self.pos)),
+                        }
+                    }
+                    Some(Open::Object(fields, key)) => {
+                        fields.push((std::mem::take(key), value));
+                        match self.peek() {
+                            Some(',') => false,
+                            Some('}') => true,
+                            _ => {
+                                return Err(format!("expected ',' or '}}' at offset {}", self.pos));
+                            }
+                        }
+                    }
+                };
+                self.pos += 1;
+
+                if !closed {
+                    // Another item follows; objects need its key first
+                    if let Some(Open::Object(_, key)) = open.last_mut() {
+                        *key = self.parse_key()?;
+                    }
+                    break;
+                }
+                value = match open.pop() {
+                    Some(Open::Array(items)) => Value::Array(items),
+                    Some(Open::Object(fields, _)) => Value::Object(fields),
+                    None => unreachable!("a container was just closed"),
+                };
+            }
+        }
+    }
+
+    /// An object key and its `:`
+    fn parse_key(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        let key = self.parse_string()?;
+        self.skip_whitespace();
+        self.expect(':')?;
+        Ok(key)
+    }
+
+    fn parse_literal(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for expected in word.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.pos += 1;
+        }
+
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse()
+            .map(Value::Number)
+            .map_err(|_| format!("invalid number '{}' at offset {}", text, start))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err("unterminated string".into());
+            };
+            self.pos += 1;
+
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let Some(escaped) = self.peek() else {
+                        return Err("unterminated string".into());
+                    };
+                    self.pos += 1;
+                    match escaped {
+                        '"' => out.push('"'),
+                        '\\' => out.push('\\'),
+                        '/' => out.push('/'),
+                        'b' => out.push('\u{08}'),
+                        'f' => out.push('\u{0c}'),
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'u' => out.push(self.parse_unicode_escape()?),
+                        other => {
+                            return Err(format!(
+                                "invalid escape '\\{}' at offset {}",
+                                other, self.pos
+                            ));
+                        }
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// Parse the `XXXX` after `\u`, combining surrogate pairs
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.parse_hex4()?;
+        if (0xD800..0xDC00).contains(&high) {
+            self.expect('\\')?;
+            self.expect('u')?;
+            let low = self.parse_hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err("invalid surrogate pair".into());
+            }
+            let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+            return char::from_u32(code).ok_or_else(|| "invalid surrogate pair".to_string());
+        }
+
+        char::from_u32(high).ok_or_else(|| format!("invalid \\u escape at offset {}", self.pos))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        if self.pos + 4 > self.chars.len() {
+            return Err("truncated \\u escape".into());
+        }
+
+        let hex: String = self.chars[self.pos..self.pos + 4].iter().collect();
+        self.pos += 4;
+        u32::from_str_radix(&hex, 16).map_err(|_| format!("invalid \\u escape '{}'", hex))
+    }
+}
+
+/// An array or object still being parsed
+enum Open {
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>, String), // With the key of the next value
+}
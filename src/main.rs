@@ -1,13 +1,127 @@
 // src/main.rs
 
+mod date;
+mod json;
+mod sync_tree;
 mod tree;
+mod xml;
 
-use crate::tree::Tree;
-use std::io::{self, Write};
+use crate::date::Date;
+use crate::tree::{DisplayStyle, DonePolicy, TitleCaseMode, Tree, TreeError};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
 
 fn main() {
+    let usage = || -> ! {
+        eprintln!("usage: treedesk-rs [--keep-going] [--autosave <path>] [script]");
+        process::exit(2);
+    };
+
+    let mut keep_going = false;
+    let mut autosave_path = None;
+    let mut script = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--keep-going" => keep_going = true,
+            "--autosave" => match args.next() {
+                Some(path) if autosave_path.is_none() => autosave_path = Some(PathBuf::from(path)),
+                _ => usage(),
+            },
+            _ if script.is_none() && !arg.starts_with("--") => script = Some(arg),
+            _ => usage(),
+        }
+    }
+
     let mut tree = Tree::new();
 
+    // Pick up where the last autosaved session left off
+    let mut autosave = autosave_path.map(|path| {
+        if path.exists() {
+            match Tree::load_json(&path) {
+                Ok(loaded) => {
+                    tree = loaded;
+                    println!("Loaded tree from {}", path.display());
+                }
+                Err(e) => {
+                    // Don't autosave over a file we couldn't read
+                    eprintln!("Error: failed to load {}: {}", path.display(), e);
+                    process::exit(1);
+                }
+            }
+        }
+        Autosave::new(path, &tree)
+    });
+
+    // A script file, or commands piped in, run without the prompt
+    let ok = match script {
+        Some(path) => match fs::File::open(&path) {
+            Ok(file) => {
+                let mut reader = io::BufReader::new(file);
+                run_batch(
+                    |line| reader.read_line(line),
+                    &mut tree,
+                    &mut autosave,
+                    keep_going,
+                )
+            }
+            Err(e) => {
+                eprintln!("Error: failed to open {}: {}", path, e);
+                false
+            }
+        },
+        None if !io::stdin().is_terminal() => run_batch(
+            |line| io::stdin().read_line(line),
+            &mut tree,
+            &mut autosave,
+            keep_going,
+        ),
+        None => {
+            run_repl(&mut tree, &mut autosave);
+            true
+        }
+    };
+
+    if !ok {
+        process::exit(1);
+    }
+}
+
+/// `--autosave`: writes the tree back to `path` whenever a command changed it
+struct Autosave {
+    path: PathBuf,
+    saved: String, // JSON as of the last save (or load)
+}
+
+impl Autosave {
+    fn new(path: PathBuf, tree: &Tree) -> Self {
+        let saved = tree.to_json_string(false);
+        Autosave { path, saved }
+    }
+
+    /// Save if the tree differs from what was last written, so read-only
+    /// commands never touch the file. A failed save only warns, and is
+    /// retried after the next command.
+    fn after_command(&mut self, tree: &Tree) {
+        let json = tree.to_json_string(false);
+        if json == self.saved {
+            return;
+        }
+
+        match tree.save_json(&self.path) {
+            Ok(()) => self.saved = json,
+            Err(e) => eprintln!("Warning: autosave to {} failed: {}", self.path.display(), e),
+        }
+    }
+}
+
+fn run_repl(tree: &mut Tree, autosave: &mut Option<Autosave>) {
+    let mut aliases = default_aliases();
+
     println!("treedesk-rs REPL");
     println!("Type 'help' for commands, 'quit' to exit.\n");
 
@@ -43,8 +157,12 @@ fn main() {
         }
 
         // Dispatch command
-        match handle_command(line, &mut tree) {
-            Ok(()) => {}
+        match handle_command(line, tree, &mut aliases) {
+            Ok(()) => {
+                if let Some(autosave) = autosave {
+                    autosave.after_command(tree);
+                }
+            }
             Err(msg) => eprintln!("Error: {}", msg),
         }
     }
@@ -52,7 +170,164 @@ fn main() {
     println!("Goodbye! >_<");
 }
 
-fn handle_command(line: &str, tree: &mut Tree) -> Result<(), String> {
+/// Run commands line by line without a prompt. Blank lines and `#` comments
+/// are skipped. Stops at the first failing command unless `keep_going`;
+/// returns whether every command succeeded.
+///
+/// Lines come from `read_line` one at a time (rather than a held stdin
+/// lock), so a `confirm` prompt on piped input answers from the next line.
+fn run_batch(
+    mut read_line: impl FnMut(&mut String) -> io::Result<usize>,
+    tree: &mut Tree,
+    autosave: &mut Option<Autosave>,
+    keep_going: bool,
+) -> bool {
+    let mut aliases = default_aliases();
+    let mut ok = true;
+    let mut line_number = 0;
+    loop {
+        let mut line = String::new();
+        match read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => line_number += 1,
+            Err(e) => {
+                eprintln!("Error reading input: {}", e);
+                return false;
+            }
+        }
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.eq_ignore_ascii_case("quit") || line.eq_ignore_ascii_case("exit") {
+            break;
+        }
+
+        match handle_command(line, tree, &mut aliases) {
+            Ok(()) => {
+                if let Some(autosave) = autosave {
+                    autosave.after_command(tree);
+                }
+            }
+            Err(msg) => {
+                eprintln!("Error (line {}): {}", line_number, msg);
+                ok = false;
+                if !keep_going {
+                    break;
+                }
+            }
+        }
+    }
+
+    ok
+}
+
+/// Built-in command names; aliases may only shadow these with `--force`
+const COMMANDS: &[&str] = &[
+    "help",
+    "alias",
+    "root",
+    "show",
+    "child",
+    "find-path",
+    "ensure",
+    "toggle",
+    "complete",
+    "uncomplete",
+    "rename",
+    "rename-undo",
+    "case",
+    "undo",
+    "redo",
+    "delete",
+    "clear",
+    "copy",
+    "move",
+    "move-up",
+    "move-down",
+    "swap",
+    "promote",
+    "demote",
+    "priority",
+    "weight",
+    "due",
+    "overdue",
+    "sort",
+    "reverse",
+    "list",
+    "get",
+    "contains",
+    "summary",
+    "count",
+    "depth",
+    "stats",
+    "density",
+    "reindex",
+    "autoarchive",
+    "prune",
+    "search",
+    "find-any",
+    "find-all",
+    "policy",
+    "save",
+    "load",
+    "merge",
+    "tags",
+    "tag",
+    "untag",
+    "batch",
+    "endbatch",
+    "bc",
+    "path",
+    "lca",
+    "distance",
+    "fixdone",
+    "load-jsonl",
+    "import",
+    "export",
+    "leaves",
+    "queue",
+    "report",
+    "maxchildren",
+    "next-sib",
+    "prev-sib",
+    "ancestors",
+    "siblings",
+    "copy-outline",
+    "velocity",
+    "check",
+    "progress",
+    "highlight",
+];
+
+/// Session aliases, shortcut -> command line it expands to
+type Aliases = BTreeMap<String, String>;
+
+fn default_aliases() -> Aliases {
+    [
+        ("ls", "show"),
+        ("rm", "delete"),
+        ("mv", "move"),
+        ("cp", "copy"),
+    ]
+    .into_iter()
+    .map(|(name, target)| (name.to_string(), target.to_string()))
+    .collect()
+}
+
+fn handle_command(line: &str, tree: &mut Tree, aliases: &mut Aliases) -> Result<(), String> {
+    // Expand an alias once; its target is a built-in, so this can't loop
+    let mut parts = line.splitn(2, char::is_whitespace);
+    let expanded;
+    let line = match parts.next().and_then(|name| aliases.get(name)) {
+        Some(target) => {
+            expanded = format!("{} {}", target, parts.next().unwrap_or(""));
+            expanded.trim_end()
+        }
+        None => line,
+    };
+
     // Split once into command + rest
     let mut parts = line.splitn(2, char::is_whitespace);
     let cmd = parts.next().unwrap();
@@ -61,6 +336,55 @@ fn handle_command(line: &str, tree: &mut Tree) -> Result<(), String> {
     match cmd {
         "help" => {
             print_help();
+            if !aliases.is_empty() {
+                println!("Aliases:");
+                for (name, target) in aliases.iter() {
+                    println!("  {} = {}", name, target);
+                }
+            }
+        }
+
+        "alias" => {
+            // expect: alias [--force] <name> <target...>
+            if args.is_empty() {
+                if aliases.is_empty() {
+                    println!("No aliases defined.");
+                }
+                for (name, target) in aliases.iter() {
+                    println!("{} = {}", name, target);
+                }
+                return Ok(());
+            }
+
+            let (force, rest) = match args.strip_prefix("--force") {
+                Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+                    (true, rest.trim_start())
+                }
+                _ => (false, args),
+            };
+            let mut parts = rest.splitn(2, char::is_whitespace);
+            let name = parts.next().unwrap_or("");
+            let target = parts.next().unwrap_or("").trim();
+            if name.is_empty() || target.is_empty() {
+                return Err("usage: alias [--force] <name> <command> [args]".into());
+            }
+
+            let target_cmd = target.split_whitespace().next().unwrap_or("");
+            if !COMMANDS.contains(&target_cmd) {
+                return Err(format!("unknown command: {}", target_cmd));
+            }
+            if name == "alias" || name == "quit" || name == "exit" {
+                return Err(format!("'{}' cannot be aliased", name));
+            }
+            if COMMANDS.contains(&name) && !force {
+                return Err(format!(
+                    "'{}' is a built-in command (use 'alias --force' to shadow it)",
+                    name
+                ));
+            }
+
+            aliases.insert(name.to_string(), target.to_string());
+            println!("Alias {} = {}", name, target);
         }
 
         "root" => {
@@ -71,56 +395,264 @@ fn handle_command(line: &str, tree: &mut Tree) -> Result<(), String> {
             println!("Added root node with id {}", id);
         }
 
-        "show" => {
-            println!("{}", tree);
-        }
+        "show" => match args {
+            "" => {
+                tree.render(&mut io::stdout().lock())
+                    .map_err(|e| format!("failed to write output: {}", e))?;
+                println!();
+            }
+            "compact" => println!("{}", tree.to_compact_string()),
+            "--done" | "--pending" => {
+                let want_done = args == "--done";
+                let mut out = String::new();
+                tree.fmt_filtered(|node| node.done == want_done, &mut out)
+                    .map_err(|e| format!("failed to format tree: {}", e))?;
+                println!("{}", out);
+            }
+            _ if args
+                .split_whitespace()
+                .all(|flag| flag == "--unicode" || flag == "--color") =>
+            {
+                let flags: Vec<&str> = args.split_whitespace().collect();
+                let style = if flags.contains(&"--unicode") {
+                    DisplayStyle::Unicode
+                } else {
+                    DisplayStyle::Plain
+                };
+
+                // Escape codes only go to a terminal, never into redirected output
+                let mut out = String::new();
+                if flags.contains(&"--color") && io::stdout().is_terminal() {
+                    tree.fmt_colored(style, &mut out)
+                } else {
+                    tree.fmt_styled(style, &mut out)
+                }
+                .map_err(|e| format!("failed to format tree: {}", e))?;
+                println!("{}", out);
+            }
+            _ if args.starts_with("--depth") => {
+                let depth: usize = args["--depth".len()..]
+                    .trim()
+                    .parse()
+                    .map_err(|_| "usage: show --depth <n>".to_string())?;
+
+                let mut out = String::new();
+                tree.fmt_pretty_limited(depth, &mut out)
+                    .map_err(|e| format!("failed to format tree: {}", e))?;
+                println!("{}", out);
+            }
+            id_str => {
+                let id: u32 = id_str.parse().map_err(|_| {
+                    "usage: show [compact|--unicode|--color|--done|--pending|--depth <n>|<id>]"
+                        .to_string()
+                })?;
+                match tree.subtree_string(id) {
+                    Some(subtree) => println!("{}", subtree),
+                    None => return Err(format!("id {} not found", id)),
+                }
+            }
+        },
 
         "child" => {
             // expect: child <parent_id> <title>
             let mut parts = args.splitn(2, char::is_whitespace);
-            let parent_id_str = parts.next().ok_or("usage: child <parent_id> <title>")?;
+            let parent_id_str = parts
+                .next()
+                .ok_or("usage: child <parent_id|/path> <title>")?;
             let title = parts
                 .next()
-                .ok_or("usage: child <parent_id> <title>")?
+                .ok_or("usage: child <parent_id|/path> <title>")?
                 .trim();
             if title.is_empty() {
                 return Err("title cannot be empty".into());
             }
 
-            let parent_id: u32 = parent_id_str
-                .parse()
-                .map_err(|_| "parent_id must be a number".to_string())?;
+            // A leading '/' addresses the parent by its title path
+            let parent_id: u32 = match parent_id_str.strip_prefix('/') {
+                Some(path) => tree
+                    .lookup_path(&split_title_path(path))
+                    .map_err(|e| e.to_string())?,
+                None => parent_id_str
+                    .parse()
+                    .map_err(|_| "parent_id must be a number".to_string())?,
+            };
 
             match tree.add_child(parent_id, title.to_string()) {
-                Some(id) => {
+                Ok(id) => {
                     println!("Added child node with id {}", id);
                 }
-                None => {
+                Err(TreeError::NotFound(_)) => {
                     return Err(format!("parent_id {} not found", parent_id));
                 }
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+
+        "find-path" => {
+            // expect: find-path /<root>/<segment>/...
+            let path = args
+                .strip_prefix('/')
+                .ok_or("usage: find-path /<root>/<segment>/...")?;
+            let id = tree
+                .lookup_path(&split_title_path(path))
+                .map_err(|e| e.to_string())?;
+            print_nodes(tree, &[id]);
+        }
+
+        "ensure" => {
+            // expect: ensure /<root>/<segment>/...
+            let path = args
+                .strip_prefix('/')
+                .ok_or("usage: ensure /<root>/<segment>/...")?;
+            let mut segments: Vec<&str> = path.split('/').map(str::trim).collect();
+            if segments.iter().any(|segment| segment.is_empty()) {
+                return Err("path segments cannot be empty".into());
+            }
+
+            let root_title = segments.remove(0);
+            let (id, created) = tree
+                .ensure_path(root_title, &segments)
+                .map_err(|e| format!("could not create the full path: {}", e))?;
+            if created.is_empty() {
+                println!("Path already exists (id {})", id);
+            } else {
+                let created: Vec<String> = created.iter().map(|id| id.to_string()).collect();
+                println!("Ensured node {} (created: {})", id, created.join(", "));
             }
         }
 
         "toggle" => {
-            let id_str = args;
+            // expect: toggle [--local] <id>
+            let (local, id_str) = match args.strip_prefix("--local") {
+                Some(rest) => (true, rest.trim()),
+                None => (false, args),
+            };
             if id_str.is_empty() {
-                return Err("usage: toggle <id>".into());
+                return Err("usage: toggle [--local] <id>".into());
             }
 
             let id: u32 = id_str
                 .parse()
                 .map_err(|_| "id must be a number".to_string())?;
 
-            match tree.toggle(id) {
-                true => {
-                    println!("Toggled done flag for node {}", id);
+            if local {
+                if !tree.toggle_local(id) {
+                    return Err(format!("id {} not found", id));
+                }
+                let done = tree.get(id).is_some_and(|node_ref| node_ref.borrow().done);
+                println!(
+                    "Toggled node {} (parents untouched): now {}",
+                    id,
+                    if done { "done" } else { "todo" }
+                );
+                return Ok(());
+            }
+
+            match tree.toggle_get(id) {
+                Some(done) => {
+                    println!(
+                        "Toggled node {}: now {}",
+                        id,
+                        if done { "done" } else { "todo" }
+                    );
                 }
-                false => {
+                None => {
                     return Err(format!("id {} not found", id));
                 }
             }
         }
 
+        "complete" | "uncomplete" => {
+            if args.is_empty() {
+                return Err(format!("usage: {} <id>", cmd));
+            }
+
+            let id: u32 = args
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+
+            let found = if cmd == "complete" {
+                tree.complete_subtree(id)
+            } else {
+                tree.uncomplete_subtree(id)
+            };
+            if !found {
+                return Err(format!("id {} not found", id));
+            }
+
+            println!(
+                "Marked node {} and its subtree as {}",
+                id,
+                if cmd == "complete" { "done" } else { "todo" }
+            );
+        }
+        "rename" => {
+            // expect: rename <id> <new title>
+            let mut parts = args.splitn(2, char::is_whitespace);
+            let id_str = parts.next().ok_or("usage: rename <id> <new title>")?;
+            let title = parts.next().ok_or("usage: rename <id> <new title>")?.trim();
+            if title.is_empty() {
+                return Err("title cannot be empty".into());
+            }
+
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+
+            tree.rename(id, title.to_string())
+                .map_err(|e| e.to_string())?;
+            println!("Renamed node {}", id);
+        }
+
+        "rename-undo" => {
+            let id_str = args;
+            if id_str.is_empty() {
+                return Err("usage: rename-undo <id>".into());
+            }
+
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+
+            match tree.rename_undo(id) {
+                Some(title) => println!("Restored title of node {} to '{}'", id, title),
+                None => return Err(format!("nothing to undo for node {}", id)),
+            }
+        }
+
+        "case" => {
+            let mode = match args {
+                "sentence" => TitleCaseMode::Sentence,
+                "title" => TitleCaseMode::Title,
+                "upper" => TitleCaseMode::Upper,
+                "lower" => TitleCaseMode::Lower,
+                _ => return Err("usage: case <sentence|title|upper|lower>".into()),
+            };
+
+            let changed = tree.apply_title_case(mode);
+            println!("Re-cased {} title(s)", changed);
+        }
+
+        "undo" => {
+            if !tree.can_undo() {
+                return Err("nothing to undo".into());
+            }
+            if !tree.undo() {
+                return Err("the last change no longer applies; dropped it from history".into());
+            }
+            println!("Undid last change");
+        }
+
+        "redo" => {
+            if !tree.can_redo() {
+                return Err("nothing to redo".into());
+            }
+            if !tree.redo() {
+                return Err("the undone change no longer applies; dropped it from history".into());
+            }
+            println!("Redid last undone change");
+        }
+
         "delete" => {
             let id_str = args;
             if id_str.is_empty() {
@@ -132,20 +664,64 @@ fn handle_command(line: &str, tree: &mut Tree) -> Result<(), String> {
                 .map_err(|_| "id must be a number".to_string())?;
 
             match tree.delete(id) {
-                true => {
+                Ok(()) => {
                     println!("Deleted node {}", id);
                 }
-                false => {
-                    return Err(format!("id {} not found", id));
+                Err(e) => {
+                    return Err(e.to_string());
+                }
+            }
+        }
+
+        "clear" => {
+            if tree.node_count() == 0 {
+                println!("Tree is already empty");
+            } else if confirm(&format!(
+                "Delete all {} node(s)? This cannot be undone. [y/N] ",
+                tree.node_count()
+            )) {
+                tree.clear();
+                println!("Cleared the tree");
+            } else {
+                println!("Cancelled");
+            }
+        }
+
+        "copy" => {
+            // expect: copy <id> <new_parent_id>
+            let mut parts = args.split_whitespace();
+            let id_str = parts.next().ok_or("usage: copy <id> <new_parent_id>")?;
+            let parent_id_str = parts.next().ok_or("usage: copy <id> <new_parent_id>")?;
+
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+
+            let new_parent_id: u32 = parent_id_str
+                .parse()
+                .map_err(|_| "new_parent_id must be a number".to_string())?;
+
+            match tree.duplicate(id, new_parent_id) {
+                Ok(copy_id) => println!(
+                    "Copied node {} under {} as new node {}",
+                    id, new_parent_id, copy_id
+                ),
+                Err(e @ TreeError::ChildLimit { .. }) => return Err(e.to_string()),
+                Err(_) => {
+                    return Err(format!(
+                        "failed to copy node {} under {} (check ids)",
+                        id, new_parent_id
+                    ));
                 }
             }
         }
 
         "move" => {
-            // expect: move <id> <new_parent_id>
+            // expect: move <id> <new_parent_id> [index]
+            let usage = "usage: move <id> <new_parent_id> [index]";
             let mut parts = args.split_whitespace();
-            let id_str = parts.next().ok_or("usage: move <id> <new_parent_id>")?;
-            let parent_id_str = parts.next().ok_or("usage: move <id> <new_parent_id>")?;
+            let id_str = parts.next().ok_or(usage)?;
+            let parent_id_str = parts.next().ok_or(usage)?;
 
             let id: u32 = id_str
                 .parse()
@@ -155,78 +731,1312 @@ fn handle_command(line: &str, tree: &mut Tree) -> Result<(), String> {
                 .parse()
                 .map_err(|_| "new_parent_id must be a number".to_string())?;
 
-            if tree.move_node(id, new_parent_id) {
-                println!("Moved node {} under new parent {}", id, new_parent_id);
-            } else {
-                return Err(format!(
-                    "failed to move node {} under new parent {} (check ids and for cycles)",
-                    id, new_parent_id
-                ));
+            let index: usize = match parts.next() {
+                Some(index_str) => index_str
+                    .parse()
+                    .map_err(|_| "index must be a number".to_string())?,
+                None => usize::MAX,
+            };
+
+            match tree.move_node_at(id, new_parent_id, index) {
+                Ok(()) => println!("Moved node {} under new parent {}", id, new_parent_id),
+                Err(e @ TreeError::ChildLimit { .. }) => return Err(e.to_string()),
+                Err(_) => {
+                    return Err(format!(
+                        "failed to move node {} under new parent {} (check ids and for cycles)",
+                        id, new_parent_id
+                    ));
+                }
             }
         }
 
-        "get" => {
-            // simple peek: get <id>
-            let id_str = args;
-            if id_str.is_empty() {
-                return Err("usage: get <id>".into());
+        "move-up" | "move-down" => {
+            if args.is_empty() {
+                return Err(format!("usage: {} <id>", cmd));
             }
 
-            let id: u32 = id_str
+            let id: u32 = args
                 .parse()
                 .map_err(|_| "id must be a number".to_string())?;
-            if let Some(node_ref) = tree.get(id) {
-                let node = node_ref.borrow();
+            if tree.get(id).is_none() {
+                return Err(format!("id {} not found", id));
+            }
+
+            let moved = if cmd == "move-up" {
+                tree.move_up(id)
+            } else {
+                tree.move_down(id)
+            };
+            if moved {
                 println!(
-                    "[{}] {} (id: {})",
-                    if node.done { "x" } else { " " },
-                    node.title,
-                    node.id
+                    "Moved node {} {}",
+                    id,
+                    if cmd == "move-up" { "up" } else { "down" }
                 );
-                println!("children: {}", node.children.len());
             } else {
-                return Err(format!("id {} not found", id));
+                return Err(format!(
+                    "node {} is already {} among its siblings",
+                    id,
+                    if cmd == "move-up" { "first" } else { "last" }
+                ));
             }
         }
 
-        other => {
-            return Err(format!("unknown command: {other} (try 'help')"));
+        "swap" => {
+            // expect: swap <id1> <id2>
+            let mut parts = args.split_whitespace();
+            let (Some(a_str), Some(b_str), None) = (parts.next(), parts.next(), parts.next())
+            else {
+                return Err("usage: swap <id1> <id2>".into());
+            };
+
+            let a: u32 = a_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            let b: u32 = b_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            for id in [a, b] {
+                if tree.get(id).is_none() {
+                    return Err(format!("id {} not found", id));
+                }
+            }
+            if a == b {
+                return Err("cannot swap a node with itself".into());
+            }
+
+            if !tree.swap(a, b) {
+                return Err(format!(
+                    "cannot swap nodes {} and {} (one contains the other)",
+                    a, b
+                ));
+            }
+            println!("Swapped nodes {} and {}", a, b);
         }
-    }
 
-    Ok(())
-}
+        "promote" | "demote" => {
+            if args.is_empty() {
+                return Err(format!("usage: {} <id>", cmd));
+            }
 
-fn print_help() {
-    println!(
-        "\
-Commands:
-  show
-      Show the whole tree.
+            let id: u32 = args
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            if tree.get(id).is_none() {
+                return Err(format!("id {} not found", id));
+            }
 
-  root <title>
-      Add a new root node.
+            let moved = if cmd == "promote" {
+                tree.promote(id)
+            } else {
+                tree.demote(id)
+            };
+            if !moved {
+                return Err(if cmd == "promote" {
+                    format!(
+                        "cannot promote node {} (it is a root, or its new parent is full)",
+                        id
+                    )
+                } else {
+                    format!(
+                        "cannot demote node {} (it has no previous sibling, or that sibling is full)",
+                        id
+                    )
+                });
+            }
+            println!("Moved node {}", id);
+        }
 
-  child <parent_id> <title>
-      Add a child under the given parent.
+        "priority" => {
+            // expect: priority <id> <level>
+            let mut parts = args.split_whitespace();
+            let id_str = parts.next().ok_or("usage: priority <id> <level>")?;
+            let level_str = parts.next().ok_or("usage: priority <id> <level>")?;
 
-  toggle <id>
-      Toggle the 'done' flag for a node. Auto-completes parents if all children done.
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            let level: u8 = level_str
+                .parse()
+                .map_err(|_| "level must be a number from 0 to 255".to_string())?;
 
-  delete <id>
-      Delete a node and its subtree.
+            if !tree.set_priority(id, level) {
+                return Err(format!("id {} not found", id));
+            }
+            println!("Set priority of node {} to {}", id, level);
+        }
 
-  move <id> <new_parent_id>
-      Move a node to a new parent (fails if it would create a cycle).
+        "weight" => {
+            // expect: weight <id> <n>
+            let mut parts = args.split_whitespace();
+            let id_str = parts.next().ok_or("usage: weight <id> <n>")?;
+            let weight_str = parts.next().ok_or("usage: weight <id> <n>")?;
 
-  get <id>
-      Show a single node and how many children it has.
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            let weight: u32 = weight_str
+                .parse()
+                .ok()
+                .filter(|&weight| weight > 0)
+                .ok_or("weight must be a positive number")?;
 
-  help
-      Show this help.
+            if !tree.set_weight(id, weight) {
+                return Err(format!("id {} not found", id));
+            }
+            println!("Set weight of node {} to {}", id, weight);
+        }
 
-  quit | exit
-      Leave the REPL.
-"
-    );
+        "due" => {
+            // expect: due <id> <YYYY-MM-DD|none>
+            let mut parts = args.split_whitespace();
+            let id_str = parts.next().ok_or("usage: due <id> <YYYY-MM-DD|none>")?;
+            let date_str = parts.next().ok_or("usage: due <id> <YYYY-MM-DD|none>")?;
+
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            let due = match date_str {
+                "none" => None,
+                _ => Some(date_str.parse::<Date>()?),
+            };
+
+            if !tree.set_due(id, due) {
+                return Err(format!("id {} not found", id));
+            }
+            match due {
+                Some(due) => println!("Node {} is due {}", id, due),
+                None => println!("Cleared the due date of node {}", id),
+            }
+        }
+
+        "overdue" => {
+            print_nodes(tree, &tree.overdue(Date::today()));
+        }
+
+        "sort" => {
+            // expect: sort [id] [--by-id|--by-priority]
+            let usage = "usage: sort [id] [--by-id|--by-priority]";
+            let mut target = None;
+            let mut by_title = true;
+            let mut by_priority = false;
+            for part in args.split_whitespace() {
+                match part {
+                    "--by-id" => by_title = false,
+                    "--by-priority" => by_priority = true,
+                    _ => {
+                        let id: u32 = part.parse().map_err(|_| usage.to_string())?;
+                        target = Some(id);
+                    }
+                }
+            }
+
+            match target {
+                Some(id) => {
+                    let found = if by_priority {
+                        tree.sort_children_by_priority(id)
+                    } else {
+                        tree.sort_children(id, by_title)
+                    };
+                    if !found {
+                        return Err(format!("id {} not found", id));
+                    }
+                    println!("Sorted children of node {}", id);
+                }
+                None if by_priority => {
+                    return Err("--by-priority needs a node id".into());
+                }
+                None => {
+                    tree.sort_roots(by_title);
+                    println!("Sorted roots");
+                }
+            }
+        }
+
+        "reverse" => {
+            // expect: reverse [id]
+            if args.is_empty() {
+                tree.reverse_children(None);
+                println!("Reversed roots");
+            } else {
+                let id: u32 = args
+                    .parse()
+                    .map_err(|_| "usage: reverse [id]".to_string())?;
+                if !tree.reverse_children(Some(id)) {
+                    return Err(format!("id {} not found", id));
+                }
+                println!("Reversed children of node {}", id);
+            }
+        }
+
+        "list" => {
+            let nodes = tree.list();
+            if nodes.is_empty() {
+                println!("Tree is empty");
+            }
+            for (n, (id, title, done)) in nodes.iter().enumerate() {
+                println!(
+                    "{}. [{}] {} (id: {})",
+                    n + 1,
+                    if *done { "x" } else { " " },
+                    title,
+                    id
+                );
+            }
+        }
+
+        "get" => {
+            // simple peek: get [--json] <id>
+            let (json, id_str) = match args.strip_prefix("--json") {
+                Some(rest) => (true, rest.trim()),
+                None => (false, args),
+            };
+            if id_str.is_empty() {
+                return Err("usage: get [--json] <id>".into());
+            }
+
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            if json {
+                let json = tree
+                    .node_json(id)
+                    .ok_or_else(|| format!("id {} not found", id))?;
+                println!("{}", json);
+            } else if let Some(node_ref) = tree.get(id) {
+                let node = node_ref.borrow();
+                println!(
+                    "[{}] {} (id: {})",
+                    if node.done { "x" } else { " " },
+                    node.title,
+                    node.id
+                );
+                println!("children: {}", node.children.len());
+                if let Some(due) = node.due {
+                    println!("due:      {}", due);
+                }
+                println!("created:  {}", format_ago(node.created_at));
+                println!("modified: {}", format_ago(node.modified_at));
+            } else {
+                return Err(format!("id {} not found", id));
+            }
+        }
+
+        "contains" => {
+            // expect: contains <root_id> <target_id>
+            let mut parts = args.split_whitespace();
+            let root_id_str = parts
+                .next()
+                .ok_or("usage: contains <root_id> <target_id>")?;
+            let target_id_str = parts
+                .next()
+                .ok_or("usage: contains <root_id> <target_id>")?;
+
+            let root_id: u32 = root_id_str
+                .parse()
+                .map_err(|_| "root_id must be a number".to_string())?;
+            let target_id: u32 = target_id_str
+                .parse()
+                .map_err(|_| "target_id must be a number".to_string())?;
+
+            match tree.subtree_contains(root_id, target_id) {
+                Some(true) => println!("Node {} is inside the subtree of {}", target_id, root_id),
+                Some(false) => println!(
+                    "Node {} is not inside the subtree of {}",
+                    target_id, root_id
+                ),
+                None => return Err(format!("id {} not found", root_id)),
+            }
+        }
+
+        "summary" => {
+            println!("{}", tree.summary());
+        }
+
+        "count" => {
+            if args.is_empty() {
+                return Err("usage: count <id>".into());
+            }
+
+            let id: u32 = args
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+
+            match tree.subtree_size(id) {
+                Some(size) => println!("Subtree of node {} has {} node(s)", id, size),
+                None => return Err(format!("id {} not found", id)),
+            }
+        }
+
+        "depth" => {
+            if args.is_empty() {
+                return Err("usage: depth <id>".into());
+            }
+
+            let id: u32 = args
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+
+            match (tree.depth(id), tree.height(id)) {
+                (Some(depth), Some(height)) => {
+                    println!("Node {}: depth {}, height {}", id, depth, height)
+                }
+                _ => return Err(format!("id {} not found", id)),
+            }
+        }
+
+        "stats" => {
+            let stats = tree.stats();
+            let percent_done = if stats.total_nodes == 0 {
+                0.0
+            } else {
+                stats.done_nodes as f64 * 100.0 / stats.total_nodes as f64
+            };
+
+            println!("Nodes:     {}", stats.total_nodes);
+            println!("Done:      {} ({:.1}%)", stats.done_nodes, percent_done);
+            println!("Leaves:    {}", stats.leaf_count);
+            println!("Roots:     {}", stats.root_count);
+            println!("Max depth: {}", stats.max_depth);
+        }
+
+        "density" => {
+            let edges = tree.edge_count();
+            let branches = tree.branch_count();
+
+            println!("nodes: {}", tree.node_count());
+            println!("edges: {}", edges);
+            println!("roots: {}", tree.root_count());
+            if branches == 0 {
+                println!("avg branching: n/a (no non-leaf nodes)");
+            } else {
+                println!("avg branching: {:.2}", edges as f64 / branches as f64);
+            }
+        }
+
+        "reindex" => {
+            let count = tree.rebuild_index();
+            println!("Rebuilt index with {} nodes", count);
+        }
+
+        "autoarchive" => {
+            let (archived, skipped) = tree.archive_completed_branches();
+            for id in &archived {
+                println!("Archived node {}", id);
+            }
+            for id in &skipped {
+                println!("Could not archive node {}: the archive is full", id);
+            }
+
+            let roots = tree.completed_roots();
+            if !roots.is_empty()
+                && confirm(&format!(
+                    "{} root tree(s) are fully done. Archive them too? [y/N] ",
+                    roots.len()
+                ))
+            {
+                for id in roots {
+                    if tree.archive(id) {
+                        println!("Archived root {}", id);
+                    } else {
+                        println!("Could not archive root {}: the archive is full", id);
+                    }
+                }
+            } else if archived.is_empty() && skipped.is_empty() {
+                println!("Nothing to archive");
+            }
+        }
+
+        "prune" => match tree.prune_done() {
+            0 => println!("Nothing to prune"),
+            removed => println!("Pruned {} done node(s)", removed),
+        },
+
+        "search" => {
+            // expect: search <term> | search --regex <pattern>
+            let ids = match args.strip_prefix("--regex") {
+                Some(pattern) if pattern.is_empty() || pattern.starts_with(char::is_whitespace) => {
+                    let pattern = pattern.trim();
+                    if pattern.is_empty() {
+                        return Err("usage: search --regex <pattern>".into());
+                    }
+                    tree.search_regex(pattern)
+                        .map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?
+                }
+                _ if args.is_empty() => {
+                    return Err("usage: search <term> | search --regex <pattern>".into());
+                }
+                _ => tree.search(args),
+            };
+            if ids.is_empty() {
+                println!("No matching nodes");
+            }
+
+            for id in ids {
+                if let (Some(node_ref), Some(path)) = (tree.get(id), tree.path_to(id)) {
+                    let node = node_ref.borrow();
+                    let path: Vec<String> = path.iter().map(|id| id.to_string()).collect();
+                    println!(
+                        "[{}] {} (id: {})  path: {}",
+                        if node.done { "x" } else { " " },
+                        node.title,
+                        node.id,
+                        path.join(" > ")
+                    );
+                }
+            }
+        }
+
+        "find-any" | "find-all" => {
+            let terms: Vec<&str> = args.split_whitespace().collect();
+            if terms.is_empty() {
+                return Err(format!("usage: {} <term> [<term>...]", cmd));
+            }
+
+            let ids = if cmd == "find-any" {
+                tree.find_any(&terms)
+            } else {
+                tree.find_all(&terms)
+            };
+            print_nodes(tree, &ids);
+        }
+
+        "policy" => {
+            // expect: policy [all|any|manual]
+            let policy = match args {
+                "" => {
+                    println!("Done policy: {:?}", tree.done_policy());
+                    return Ok(());
+                }
+                "all" => DonePolicy::AllChildren,
+                "any" => DonePolicy::AnyChild,
+                "manual" => DonePolicy::Manual,
+                _ => return Err("usage: policy <all|any|manual>".into()),
+            };
+
+            tree.set_done_policy(policy);
+            println!("Done policy set to {:?}", policy);
+        }
+
+        "save" => {
+            // expect: save <path> [pretty]
+            let (path, pretty) = match args.strip_suffix(" pretty") {
+                Some(path) => (path.trim(), true),
+                None => (args, false),
+            };
+            if path.is_empty() {
+                return Err("usage: save <path> [pretty]".into());
+            }
+
+            let result = if pretty {
+                tree.save_json_pretty(Path::new(path))
+            } else {
+                tree.save_json(Path::new(path))
+            };
+            result.map_err(|e| format!("failed to save to {}: {}", path, e))?;
+            println!("Saved tree to {}", path);
+        }
+
+        "load" => {
+            let path = args;
+            if path.is_empty() {
+                return Err("usage: load <path>".into());
+            }
+
+            let loaded = Tree::load_json(Path::new(path))
+                .map_err(|e| format!("failed to load {}: {}", path, e))?;
+            *tree = loaded;
+            println!("Loaded tree from {}", path);
+        }
+
+        "merge" => {
+            let path = args;
+            if path.is_empty() {
+                return Err("usage: merge <path>".into());
+            }
+
+            let other = Tree::load_json(Path::new(path))
+                .map_err(|e| format!("failed to load {}: {}", path, e))?;
+            let root_ids = tree
+                .merge(other)
+                .map_err(|e| format!("could not merge {}: {}", path, e))?;
+            let root_ids: Vec<String> = root_ids.iter().map(|id| id.to_string()).collect();
+            println!(
+                "Merged {} root(s) from {} (new ids: {})",
+                root_ids.len(),
+                path,
+                root_ids.join(", ")
+            );
+        }
+
+        "tags" if !args.is_empty() => {
+            print_nodes(tree, &tree.find_by_tag(args));
+        }
+
+        "tags" => {
+            let histogram = tree.tag_histogram();
+            if histogram.is_empty() {
+                println!("No tags in use");
+            }
+
+            for (tag, count) in histogram {
+                println!("{:>5}  {}", count, tag);
+            }
+        }
+
+        "tag" | "untag" => {
+            // expect: tag <id> <tag>
+            let usage = format!("usage: {} <id> <tag>", cmd);
+            let mut parts = args.split_whitespace();
+            let id_str = parts.next().ok_or(usage.clone())?;
+            let tag = parts.next().ok_or(usage)?;
+
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            if tree.get(id).is_none() {
+                return Err(format!("id {} not found", id));
+            }
+
+            if cmd == "tag" {
+                if tree.add_tag(id, tag) {
+                    println!("Tagged node {} with {}", id, tag);
+                } else {
+                    println!("Node {} is already tagged {}", id, tag);
+                }
+            } else if tree.remove_tag(id, tag) {
+                println!("Removed tag {} from node {}", tag, id);
+            } else {
+                return Err(format!("node {} is not tagged {}", id, tag));
+            }
+        }
+
+        "batch" => {
+            if tree.in_batch() {
+                return Err("a batch is already open (use 'endbatch')".into());
+            }
+            tree.begin_batch();
+            println!("Batch started: done flags will be recomputed at 'endbatch'");
+        }
+
+        "endbatch" => {
+            if !tree.end_batch() {
+                return Err("no batch is open (use 'batch')".into());
+            }
+            println!("Batch ended: done flags recomputed");
+        }
+
+        "bc" => {
+            // expect: bc <id> [max_len]
+            let mut parts = args.split_whitespace();
+            let id_str = parts.next().ok_or("usage: bc <id> [max_len]")?;
+
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            let max_len: usize = match parts.next() {
+                Some(max_len_str) => max_len_str
+                    .parse()
+                    .map_err(|_| "max_len must be a number".to_string())?,
+                None => 60,
+            };
+
+            match tree.breadcrumb(id, max_len) {
+                Some(breadcrumb) => println!("{}", breadcrumb),
+                None => return Err(format!("id {} not found", id)),
+            }
+        }
+
+        "path" => {
+            if args.is_empty() {
+                return Err("usage: path <id>".into());
+            }
+
+            let id: u32 = args
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+
+            match tree.path_to(id) {
+                Some(path) => {
+                    let ids: Vec<String> = path.iter().map(|id| id.to_string()).collect();
+                    println!("{}", ids.join(" > "));
+                }
+                None => return Err(format!("id {} not found", id)),
+            }
+        }
+
+        "lca" => {
+            // expect: lca <id1> <id2>
+            let mut parts = args.split_whitespace();
+            let (Some(a_str), Some(b_str), None) = (parts.next(), parts.next(), parts.next())
+            else {
+                return Err("usage: lca <id1> <id2>".into());
+            };
+
+            let a: u32 = a_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            let b: u32 = b_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            for id in [a, b] {
+                if tree.get(id).is_none() {
+                    return Err(format!("id {} not found", id));
+                }
+            }
+
+            match tree.lca(a, b) {
+                Some(id) => print_nodes(tree, &[id]),
+                None => println!("Nodes {} and {} are in different root trees", a, b),
+            }
+        }
+
+        "distance" => {
+            // expect: distance <id1> <id2>
+            let mut parts = args.split_whitespace();
+            let (Some(a_str), Some(b_str), None) = (parts.next(), parts.next(), parts.next())
+            else {
+                return Err("usage: distance <id1> <id2>".into());
+            };
+
+            let a: u32 = a_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            let b: u32 = b_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            for id in [a, b] {
+                if tree.get(id).is_none() {
+                    return Err(format!("id {} not found", id));
+                }
+            }
+
+            match tree.distance(a, b) {
+                Some(distance) => println!("Distance between {} and {}: {}", a, b, distance),
+                None => println!("Nodes {} and {} are in different root trees", a, b),
+            }
+        }
+
+        "fixdone" => {
+            let id_str = args;
+            if id_str.is_empty() {
+                return Err("usage: fixdone <id>".into());
+            }
+
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+
+            match tree.fix_node_done(id) {
+                Some(done) => println!(
+                    "Recomputed node {}: now {}",
+                    id,
+                    if done { "done" } else { "todo" }
+                ),
+                None => return Err(format!("id {} not found", id)),
+            }
+        }
+
+        "load-jsonl" => {
+            let path = args;
+            if path.is_empty() {
+                return Err("usage: load-jsonl <path>".into());
+            }
+
+            let file =
+                fs::File::open(path).map_err(|e| format!("failed to open {}: {}", path, e))?;
+            let loaded = Tree::from_jsonl(io::BufReader::new(file))
+                .map_err(|e| format!("failed to load {}: {}", path, e))?;
+            *tree = loaded;
+            println!("Loaded tree from {}", path);
+        }
+
+        "import" => {
+            // expect: import <format> <path>
+            let mut parts = args.splitn(2, char::is_whitespace);
+            let format = parts.next().unwrap_or("");
+            let path = parts.next().unwrap_or("").trim();
+            if format.is_empty() || path.is_empty() {
+                return Err("usage: import <md|opml|outline|csv> <path>".into());
+            }
+
+            let contents =
+                fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+            let loaded = match format {
+                "md" => Tree::from_markdown(&contents),
+                "opml" => Tree::from_opml(&contents),
+                "outline" => Tree::from_outline(&contents),
+                "csv" => Tree::from_csv(&contents),
+                other => return Err(format!("unknown import format: {}", other)),
+            }
+            .map_err(|e| format!("failed to import {}: {}", path, e))?;
+            *tree = loaded;
+            println!("Imported {} from {}", format, path);
+        }
+
+        "export" => {
+            // expect: export <format> <path>
+            let mut parts = args.splitn(2, char::is_whitespace);
+            let format = parts.next().unwrap_or("");
+            let path = parts.next().unwrap_or("").trim();
+            if format.is_empty() || path.is_empty() {
+                return Err("usage: export <md|dot|tsv|csv|jsonl|svg|opml|mermaid> <path>".into());
+            }
+
+            let contents = match format {
+                "tsv" => tree.to_tsv(),
+                "csv" => tree.to_csv(),
+                "jsonl" => tree.to_jsonl(),
+                "svg" => tree.to_svg(),
+                "md" => tree.to_markdown(),
+                "dot" => tree.to_dot(),
+                "opml" => tree.to_opml(),
+                "mermaid" => tree.to_mermaid(),
+                other => return Err(format!("unknown export format: {}", other)),
+            };
+
+            fs::write(path, contents).map_err(|e| format!("failed to write {}: {}", path, e))?;
+            println!("Exported {} to {}", format, path);
+        }
+
+        "leaves" => {
+            print_nodes(tree, &tree.leaves());
+        }
+
+        "queue" => {
+            let ids = tree.pending_leaves_by_priority();
+            if ids.is_empty() {
+                println!("Nothing pending");
+            }
+
+            for (n, id) in ids.iter().enumerate() {
+                if let Some(node_ref) = tree.get(*id) {
+                    let node = node_ref.borrow();
+                    println!(
+                        "{}. [!{}] {} (id: {})",
+                        n + 1,
+                        node.priority,
+                        node.title,
+                        node.id
+                    );
+                }
+            }
+        }
+
+        "report" => match args {
+            "done" => {
+                let completed = tree.completed_flat();
+                if completed.is_empty() {
+                    println!("Nothing completed yet");
+                }
+
+                for (id, title) in completed {
+                    println!("[x] {} (id: {})", title, id);
+                }
+            }
+            _ => return Err("usage: report done".into()),
+        },
+
+        "maxchildren" => {
+            // expect: maxchildren [<n>|off]
+            match args {
+                "" => match tree.max_children() {
+                    Some(max) => println!("Max children per node: {}", max),
+                    None => println!("Max children per node: unlimited"),
+                },
+                "off" => {
+                    tree.set_max_children(None);
+                    println!("Max children per node: unlimited");
+                }
+                _ => {
+                    let max: usize = args
+                        .parse()
+                        .map_err(|_| "usage: maxchildren [<n>|off]".to_string())?;
+                    tree.set_max_children(Some(max));
+                    println!("Max children per node: {}", max);
+                }
+            }
+        }
+
+        "next-sib" | "prev-sib" => {
+            let id_str = args;
+            if id_str.is_empty() {
+                return Err(format!("usage: {} <id>", cmd));
+            }
+
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            if tree.get(id).is_none() {
+                return Err(format!("id {} not found", id));
+            }
+
+            let sibling = if cmd == "next-sib" {
+                tree.next_sibling(id)
+            } else {
+                tree.prev_sibling(id)
+            };
+            match sibling {
+                Some(sibling) => print_nodes(tree, &[sibling]),
+                None => println!(
+                    "Node {} has no {} sibling",
+                    id,
+                    if cmd == "next-sib" {
+                        "next"
+                    } else {
+                        "previous"
+                    }
+                ),
+            }
+        }
+
+        "ancestors" | "siblings" => {
+            if args.is_empty() {
+                return Err(format!("usage: {} <id>", cmd));
+            }
+
+            let id: u32 = args
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+            if tree.get(id).is_none() {
+                return Err(format!("id {} not found", id));
+            }
+
+            let ids = if cmd == "ancestors" {
+                tree.ancestors(id)
+            } else {
+                tree.siblings(id)
+            };
+            if ids.is_empty() {
+                println!("Node {} has no {}", id, cmd);
+            } else {
+                print_nodes(tree, &ids);
+            }
+        }
+
+        "copy-outline" => {
+            let id_str = args;
+            if id_str.is_empty() {
+                return Err("usage: copy-outline <id>".into());
+            }
+
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+
+            match tree.subtree_to_outline(id) {
+                Some(outline) => print!("{}", outline),
+                None => return Err(format!("id {} not found", id)),
+            }
+        }
+
+        "velocity" => {
+            let days = tree.completions_per_day();
+            if days.is_empty() {
+                println!("No completions recorded yet");
+            }
+
+            for (day, count) in days {
+                println!("{}  {} {}", day, "#".repeat(count), count);
+            }
+        }
+
+        "check" => match args {
+            "links" => {
+                let problems = tree.weak_link_report();
+                if problems.is_empty() {
+                    println!("All parent/child links are consistent");
+                }
+
+                for problem in problems {
+                    println!("{}", problem);
+                }
+            }
+            "all" => match tree.validate() {
+                Ok(()) => println!("Tree is consistent"),
+                Err(problems) => {
+                    for problem in problems {
+                        println!("{}", problem);
+                    }
+                }
+            },
+            _ => return Err("usage: check <links|all>".into()),
+        },
+
+        "progress" => {
+            // expect: progress [--weighted] <id>
+            let (weighted, id_str) = match args.strip_prefix("--weighted") {
+                Some(rest) => (true, rest.trim()),
+                None => (false, args),
+            };
+            if id_str.is_empty() {
+                return Err("usage: progress [--weighted] <id>".into());
+            }
+
+            let id: u32 = id_str
+                .parse()
+                .map_err(|_| "id must be a number".to_string())?;
+
+            let progress = if weighted {
+                tree.weighted_progress(id)
+            } else {
+                tree.progress(id)
+            };
+            match progress {
+                Some(fraction) => println!("Node {}: {:.0}% done", id, fraction * 100.0),
+                None => return Err(format!("id {} not found", id)),
+            }
+        }
+
+        "highlight" => {
+            // expect: highlight <id> | highlight off
+            if args.is_empty() {
+                return Err("usage: highlight <id> | highlight off".into());
+            }
+
+            if args.eq_ignore_ascii_case("off") {
+                tree.set_highlight(None);
+                println!("Highlight cleared");
+            } else {
+                let id: u32 = args
+                    .parse()
+                    .map_err(|_| "id must be a number".to_string())?;
+                if !tree.set_highlight(Some(id)) {
+                    return Err(format!("id {} not found", id));
+                }
+                println!("Highlighting path to node {}", id);
+            }
+        }
+
+        other => {
+            return Err(format!("unknown command: {other} (try 'help')"));
+        }
+    }
+
+    Ok(())
+}
+
+/// How long ago `time` was, coarsely: `"42s ago"`, `"2m ago"`, `"5h ago"`, `"3d ago"`
+fn format_ago(time: SystemTime) -> String {
+    let secs = SystemTime::now()
+        .duration_since(time)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+
+    match secs {
+        0..60 => format!("{}s ago", secs),
+        60..3_600 => format!("{}m ago", secs / 60),
+        3_600..86_400 => format!("{}h ago", secs / 3_600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
+
+/// Print one `[x] title (id: N)` line per id, or a note if there are none.
+fn print_nodes(tree: &Tree, ids: &[u32]) {
+    if ids.is_empty() {
+        println!("No matching nodes");
+        return;
+    }
+
+    for id in ids {
+        if let Some(node_ref) = tree.get(*id) {
+            let node = node_ref.borrow();
+            println!(
+                "[{}] {} (id: {})",
+                if node.done { "x" } else { " " },
+                node.title,
+                node.id
+            );
+        }
+    }
+}
+
+/// Split `Work/Backlog` (leading '/' already stripped) into title
+/// segments, trimming the spaces around each
+fn split_title_path(path: &str) -> Vec<&str> {
+    path.split('/').map(str::trim).collect()
+}
+
+/// Ask a yes/no question on stdin. Anything but `y`/`yes` counts as no.
+fn confirm(prompt: &str) -> bool {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap();
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    let answer = answer.trim();
+    answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes")
+}
+
+fn print_help() {
+    println!(
+        "\
+Commands:
+  show
+      Show the whole tree. [~] marks an unfinished node with some work done below it.
+
+  show [--unicode] [--color]
+      Show the whole tree with box-drawing guide lines and/or done nodes in green
+      (color only when writing to a terminal).
+
+  show compact
+      Show the tree with single-child chains joined onto one line (A > B > C).
+
+  show --done | --pending
+      Show only done (or pending) nodes, plus their ancestors for context.
+
+  show <id>
+      Show only the subtree rooted at a node.
+
+  show --depth <n>
+      Show the tree down to <n> levels below the roots (0 = roots only).
+
+  root <title>
+      Add a new root node.
+
+  child <parent_id|/path> <title>
+      Add a child under the given parent, by id or by title path (/Work/Backlog).
+
+  find-path /<root>/<segment>/...
+      Show the node at a title path, if exactly one matches.
+
+  ensure /<root>/<segment>/...
+      Find or create the titled path, reporting which nodes were created.
+
+  toggle <id>
+      Toggle the 'done' flag for a node. Auto-completes parents per the done policy.
+
+  toggle --local <id>
+      Toggle only this node, leaving its parents alone. A later change to any of
+      its children recomputes it from them, which may undo the manual flag.
+
+  complete <id>
+      Mark a node and its whole subtree done.
+
+  uncomplete <id>
+      Mark a node and its whole subtree not done.
+
+  rename <id> <new title>
+      Change a node's title.
+
+  rename-undo <id>
+      Restore the title a node had before its last rename.
+
+  case <sentence|title|upper|lower>
+      Re-case every title (simple rules: acronyms are not preserved).
+
+  undo | redo
+      Revert / re-apply the last root, child, toggle, delete, move, swap or reorder.
+
+  delete <id>
+      Delete a node and its subtree.
+
+  clear
+      Delete every node and restart ids at 1 (asks first; cannot be undone).
+
+  copy <id> <new_parent_id>
+      Copy a node and its subtree under another parent, with fresh ids.
+
+  move <id> <new_parent_id> [index]
+      Move a node to a new parent (fails if it would create a cycle).
+      With an index, insert at that position among the children instead of last.
+
+  move-up <id> | move-down <id>
+      Swap a node with its previous / next sibling.
+
+  swap <id1> <id2>
+      Exchange two nodes' places in the tree, subtrees included.
+
+  promote <id> | demote <id>
+      Outdent a node to follow its parent, or indent it under its previous sibling.
+
+  due <id> <YYYY-MM-DD|none>
+      Set (or clear) a node's due date.
+
+  overdue
+      List unfinished nodes whose due date has passed.
+
+  sort [id] [--by-id|--by-priority]
+      Sort a node's children (or the roots) by title, or by id with --by-id.
+      --by-priority orders a node's children most urgent first.
+
+  reverse [id]
+      Reverse the order of a node's children (or the roots).
+
+  priority <id> <level>
+      Set a node's priority (0-255; 0 = normal, higher is more urgent).
+
+  weight <id> <n>
+      Set a node's relative size (default 1), used by progress --weighted.
+
+  list
+      List every node on its own numbered line, in tree order.
+
+  get [--json] <id>
+      Show a single node and how many children it has (--json: id, title,
+      done, child ids and parent id as one JSON object).
+
+  contains <root_id> <target_id>
+      Check whether a node lies within another node's subtree.
+
+  summary
+      Print a one-line description of the tree.
+
+  stats
+      Print node, done, leaf and root counts and the maximum depth (roots are 0).
+
+  count <id>
+      Print how many nodes are in a node's subtree (including itself).
+
+  depth <id>
+      Show how deep a node sits (roots are 0) and how many levels lie below it.
+
+  density
+      Report node, edge, and root counts plus the average branching factor.
+
+  reindex
+      Rebuild the id index from the tree structure.
+
+  autoarchive
+      Move fully-done branches under an 'Archive' root (asks before whole roots).
+
+  prune
+      Delete every fully-done branch (undo restores them one branch at a time).
+
+  search <term>
+      Find nodes whose title contains the term (case-insensitive), with their paths.
+
+  search --regex <pattern>
+      Find nodes whose title matches a regular expression (case-sensitive; start
+      the pattern with (?i) to ignore case), with their paths.
+
+  find-any <term> [<term>...]
+  find-all <term> [<term>...]
+      List nodes whose title contains any / all of the terms (case-insensitive).
+
+  policy [all|any|manual]
+      Show or set how parents auto-complete: all children done, any child done,
+      or never automatically.
+
+  save <path> [pretty]
+      Save the tree as JSON; 'pretty' writes indented, hand-editable output.
+
+  load <path>
+      Replace the tree with one saved by 'save'.
+
+  merge <path>
+      Add the roots of a tree saved by 'save' to this tree, with fresh ids.
+
+  tags [tag]
+      Show how many nodes carry each tag, or list the nodes carrying one tag.
+
+  tag <id> <tag> | untag <id> <tag>
+      Add a tag to / remove a tag from a node.
+
+  batch | endbatch
+      Suspend done propagation for a series of edits, then recompute once.
+
+  bc <id> [max_len]
+      Show the title path to a node, shortened in the middle past max_len (default 60).
+
+  path <id>
+      Show the id path from the root down to a node (e.g. 1 > 4 > 9).
+
+  lca <id1> <id2>
+      Show the deepest node that contains both nodes.
+
+  distance <id1> <id2>
+      Count the edges on the path between two nodes (0 for the same node).
+
+  fixdone <id>
+      Recompute a node's done flag from its children, and its ancestors'.
+
+  load-jsonl <path>
+      Replace the tree with one loaded from a JSON Lines file (any line order).
+
+  export <md|dot|tsv|csv|jsonl|svg|opml|mermaid> <path>
+      Write the tree to a file in the given format.
+
+  import <md|opml|outline|csv> <path>
+      Replace the tree with one read from a Markdown task list, OPML file, an
+      indented outline as printed by show, or CSV as written by export csv.
+
+  leaves
+      List every node without children (the actual work items), in tree order.
+
+  queue
+      List pending leaf tasks as a numbered work queue, most urgent first.
+
+  report done
+      List every completed leaf task with its full path.
+
+  maxchildren [<n>|off]
+      Show, set, or remove the cap on direct children per node.
+
+  next-sib <id> | prev-sib <id>
+      Show the sibling right after / before a node.
+
+  ancestors <id>
+      List a node's ancestors, nearest parent first.
+
+  siblings <id>
+      List the other children of a node's parent (or the other roots).
+
+  copy-outline <id>
+      Print a node's subtree as a plain indented outline, ready to paste.
+
+  velocity
+      Show how many tasks were completed per day.
+
+  check links
+      Verify that parent pointers and child lists agree.
+
+  check all
+      Run every integrity check: index, reachability, cycles, links and ids.
+
+  progress [--weighted] <id>
+      Show what percentage of a node's leaf tasks are done. --weighted counts
+      each leaf by its weight.
+
+  highlight <id> | highlight off
+      Mark a node and its ancestors with '»' in 'show', or clear the mark.
+
+  alias [--force] <name> <command> [args]
+      Define a shortcut for this session ('alias' alone lists them). Built-in
+      names can only be shadowed with --force.
+
+  help
+      Show this help.
+
+  quit | exit
+      Leave the REPL.
+"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_listed_command_dispatches() {
+        for &cmd in COMMANDS {
+            let mut tree = Tree::new();
+            let mut aliases = default_aliases();
+            if let Err(msg) = handle_command(cmd, &mut tree, &mut aliases) {
+                assert!(
+                    !msg.starts_with("unknown command"),
+                    "'{}' is in COMMANDS but has no match arm",
+                    cmd
+                );
+            }
+        }
+
+        let err = handle_command("no-such-command", &mut Tree::new(), &mut default_aliases())
+            .unwrap_err();
+        assert!(err.starts_with("unknown command"));
+    }
 }
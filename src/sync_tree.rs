@@ -0,0 +1,444 @@
+// src/sync_tree.rs
+
+//! A thread-safe counterpart to `Tree`, built on `Arc<RwLock<_>>` with `Weak`
+//! parent links, for sharing node handles across threads (e.g. behind a web
+//! handler).
+//!
+//! It mirrors the core of `Tree`'s API (`add_root`, `add_child`, `toggle`,
+//! `delete`, `move_node`) but not the extras: there is no undo history,
+//! child cap, highlighting or import/export.
+//!
+//! Locking rule: at most one node lock is held at a time. Parent pointers and
+//! child lists are cloned out of a guard before the next node is locked, so
+//! walking up (propagation, cycle checks) and walking down can never wait on
+//! each other in opposite orders.
+
+use crate::tree::{DonePolicy, TreeError};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+
+/// Shared, lockable reference to a node.
+pub type SyncNodeRef<T = ()> = Arc<RwLock<SyncNode<T>>>;
+
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct SyncNode<T = ()> {
+    pub id: u32,
+    pub title: String,
+    pub done: bool,
+    pub data: T,                                   // Caller-defined payload
+    pub children: Vec<SyncNodeRef<T>>,             // Child nodes of this node
+    pub parent: Option<Weak<RwLock<SyncNode<T>>>>, // Weak pointer to the parent
+}
+
+/// Drop children iteratively, like `Node`, so deep chains can't overflow the
+/// stack.
+impl<T> Drop for SyncNode<T> {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(child) = stack.pop() {
+            // Only unwrap nodes nobody else holds; shared ones drop later
+            if let Ok(lock) = Arc::try_unwrap(child) {
+                let mut node = lock.into_inner().unwrap_or_else(PoisonError::into_inner);
+                stack.append(&mut node.children);
+            }
+        }
+    }
+}
+
+/// Read-lock a node. A panic in another thread doesn't leave the tree's
+/// links half-written (every update is a single assignment), so a poisoned
+/// lock is still safe to use.
+fn read<T>(node_ref: &SyncNodeRef<T>) -> RwLockReadGuard<'_, SyncNode<T>> {
+    node_ref.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Write-lock a node; see `read` on poisoning.
+fn write<T>(node_ref: &SyncNodeRef<T>) -> RwLockWriteGuard<'_, SyncNode<T>> {
+    node_ref.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The parent of a node, with the node's lock already released
+fn parent_of<T>(node_ref: &SyncNodeRef<T>) -> Option<SyncNodeRef<T>> {
+    let parent = read(node_ref).parent.clone();
+    parent.and_then(|parent| parent.upgrade())
+}
+
+/// A forest of titled nodes whose handles are `Send + Sync` (for `T: Send +
+/// Sync`). Structural changes take `&mut self`; wrap the tree in a `Mutex`
+/// or `RwLock` to share it, and hand out `get` handles for concurrent reads.
+pub struct SyncTree<T = ()> {
+    roots: Vec<SyncNodeRef<T>>,
+    index: HashMap<u32, SyncNodeRef<T>>,
+    next_id: u32,
+    done_policy: DonePolicy,
+}
+
+// Sharing across threads is the point of this type: fail the build if a
+// field ever makes it (or its node handles) lose `Send` or `Sync`
+const _: () = {
+    const fn assert_send_sync<S: Send + Sync>() {}
+    assert_send_sync::<SyncTree>();
+    assert_send_sync::<SyncNodeRef>();
+};
+
+impl<T> Default for SyncTree<T> {
+    fn default() -> Self {
+        SyncTree {
+            roots: Vec::new(),
+            index: HashMap::new(),
+            next_id: 1,
+            done_policy: DonePolicy::default(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T> SyncTree<T> {
+    pub fn new() -> Self {
+        SyncTree::default()
+    }
+
+    /// Change how parent completion is derived from children. Existing flags
+    /// are left alone until the next change propagates.
+    pub fn set_done_policy(&mut self, policy: DonePolicy) {
+        self.done_policy = policy;
+    }
+
+    /// Add a new root node. Returns its ID.
+    pub fn add_root(&mut self, title: impl Into<String>) -> u32
+    where
+        T: Default,
+    {
+        self.add_root_with(title, T::default())
+    }
+
+    /// Like `add_root`, with an explicit payload
+    pub fn add_root_with(&mut self, title: impl Into<String>, data: T) -> u32 {
+        let id = self.alloc_id();
+        let node = Self::new_node(id, title.into(), data, None);
+
+        self.index.insert(id, Arc::clone(&node));
+        self.roots.push(node);
+
+        id
+    }
+
+    /// Add a child under `parent_id`. Returns child ID on success
+    ///
+    /// Fails if the title is blank or the parent is missing.
+    pub fn add_child(&mut self, parent_id: u32, title: impl Into<String>) -> Result<u32, TreeError>
+    where
+        T: Default,
+    {
+        self.add_child_with(parent_id, title, T::default())
+    }
+
+    /// Like `add_child`, with an explicit payload
+    pub fn add_child_with(
+        &mut self,
+        parent_id: u32,
+        title: impl Into<String>,
+        data: T,
+    ) -> Result<u32, TreeError> {
+        let title = title.into();
+        if title.trim().is_empty() {
+            return Err(TreeError::EmptyTitle);
+        }
+
+        let parent = self.node_or_err(parent_id)?;
+        let id = self.alloc_id();
+        let child = Self::new_node(id, title, data, Some(Arc::downgrade(&parent)));
+
+        write(&parent).children.push(Arc::clone(&child));
+        self.index.insert(id, child);
+
+        // recompute done flags upward from parent
+        self.propagate_done_upward(&parent);
+
+        Ok(id)
+    }
+
+    /// Toggle the `done` flag for a node and propagate upwards per the
+    /// active `DonePolicy`. Returns `true` if found.
+    ///
+    /// Like `Tree::toggle`, propagation starts at the node itself, so a
+    /// parent's flag is recomputed from its children right away.
+    pub fn toggle(&mut self, id: u32) -> bool {
+        let Some(node_ref) = self.index.get(&id).cloned() else {
+            return false;
+        };
+
+        {
+            let mut node = write(&node_ref);
+            node.done = !node.done;
+        }
+        self.propagate_done_upward(&node_ref);
+
+        true
+    }
+
+    /// Delete a node and its subtree.
+    pub fn delete(&mut self, id: u32) -> Result<(), TreeError> {
+        let node_ref = self.node_or_err(id)?;
+
+        // 1. Detach from the parent or from roots
+        self.detach(&node_ref);
+
+        // 2. Remove from index (this node + all descendants)
+        let mut stack = vec![node_ref];
+        while let Some(node_ref) = stack.pop() {
+            let node = read(&node_ref);
+            self.index.remove(&node.id);
+            stack.extend(node.children.iter().cloned());
+        }
+
+        Ok(())
+    }
+
+    /// Move a node to a new parent, as its last child
+    ///
+    /// - Fails if `id == new_parent_id`
+    /// - Fails if either id is missing
+    /// - Fails if `new_parent` is in the subtree of `id` (would create a cycle)
+    pub fn move_node(&mut self, id: u32, new_parent_id: u32) -> Result<(), TreeError> {
+        if id == new_parent_id {
+            return Err(TreeError::SelfMove);
+        }
+
+        let node_ref = self.node_or_err(id)?;
+        let new_parent = self.node_or_err(new_parent_id)?;
+
+        if Self::is_descendant(&node_ref, &new_parent) {
+            return Err(TreeError::WouldCreateCycle);
+        }
+
+        // 1. Detach from old parent or roots
+        self.detach(&node_ref);
+
+        // 2. Attach to new parent, recomputing completion upwards from it
+        write(&node_ref).parent = Some(Arc::downgrade(&new_parent));
+        write(&new_parent).children.push(node_ref);
+        self.propagate_done_upward(&new_parent);
+
+        Ok(())
+    }
+
+    /// Get a shared handle to a node. Lock it with `read()` / `write()`;
+    /// changing `children` or `parent` through it bypasses the index.
+    pub fn get(&self, id: u32) -> Option<SyncNodeRef<T>> {
+        self.index.get(&id).cloned()
+    }
+
+    /// Number of nodes in the tree
+    pub fn node_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Ids of the root nodes, in order
+    pub fn root_ids(&self) -> Vec<u32> {
+        self.roots
+            .iter()
+            .map(|root_ref| read(root_ref).id)
+            .collect()
+    }
+
+    fn new_node(
+        id: u32,
+        title: String,
+        data: T,
+        parent: Option<Weak<RwLock<SyncNode<T>>>>,
+    ) -> SyncNodeRef<T> {
+        Arc::new(RwLock::new(SyncNode {
+            id,
+            title,
+            done: false,
+            data,
+            children: Vec::new(),
+            parent,
+        }))
+    }
+
+    fn alloc_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Look up a node, failing with `TreeError::NotFound`
+    fn node_or_err(&self, id: u32) -> Result<SyncNodeRef<T>, TreeError> {
+        self.index.get(&id).cloned().ok_or(TreeError::NotFound(id))
+    }
+
+    /// Unlink a node from its parent's children (or from `roots`), leaving
+    /// the index alone, and recompute done flags from the old parent upwards.
+    fn detach(&mut self, node_ref: &SyncNodeRef<T>) {
+        match parent_of(node_ref) {
+            Some(parent) => {
+                write(&parent)
+                    .children
+                    .retain(|child_ref| !Arc::ptr_eq(child_ref, node_ref));
+                write(node_ref).parent = None;
+                self.propagate_done_upward(&parent);
+            }
+            None => self
+                .roots
+                .retain(|root_ref| !Arc::ptr_eq(root_ref, node_ref)),
+        }
+    }
+
+    /// Return `true` if `target` is in the subtree of `root`
+    fn is_descendant(root: &SyncNodeRef<T>, target: &SyncNodeRef<T>) -> bool {
+        // Walk up from `target`, one lock at a time
+        let mut current = Some(Arc::clone(target));
+        while let Some(node_ref) = current {
+            if Arc::ptr_eq(&node_ref, root) {
+                return true;
+            }
+            current = parent_of(&node_ref);
+        }
+
+        false
+    }
+
+    /// Recalculate this node's completion based on its children (per the
+    /// active `DonePolicy`), then propagate upwards via parent links.
+    fn propagate_done_upward(&self, node_ref: &SyncNodeRef<T>) {
+        if self.done_policy == DonePolicy::Manual {
+            return;
+        }
+
+        let mut current = Some(Arc::clone(node_ref));
+        while let Some(node_ref) = current {
+            // Snapshot the children, then read each with the parent unlocked
+            let children = read(&node_ref).children.clone();
+            if !children.is_empty() {
+                let mut children_done = children.iter().map(|child_ref| read(child_ref).done);
+                let done = match self.done_policy {
+                    DonePolicy::AllChildren => children_done.all(|done| done),
+                    DonePolicy::AnyChild => children_done.any(|done| done),
+                    DonePolicy::Manual => read(&node_ref).done,
+                };
+                write(&node_ref).done = done;
+            }
+
+            current = parent_of(&node_ref);
+        }
+    }
+}
+
+/// Same layout as `Tree`'s plain `Display`: `[x] title (id: N)`, two spaces
+/// per level.
+impl<T> fmt::Display for SyncTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Explicit stack so very deep trees can't overflow the call stack
+        let mut stack: Vec<(SyncNodeRef<T>, usize)> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|root_ref| (Arc::clone(root_ref), 0))
+            .collect();
+        while let Some((node_ref, indent)) = stack.pop() {
+            let node = read(&node_ref);
+            writeln!(
+                f,
+                "{}[{}] {} (id: {})",
+                "  ".repeat(indent),
+                if node.done { "x" } else { " " },
+                node.title,
+                node.id
+            )?;
+            for child in node.children.iter().rev() {
+                stack.push((Arc::clone(child), indent + 1));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn threads_share_one_tree() {
+        let tree = Arc::new(RwLock::new(SyncTree::<()>::new()));
+        let root = tree.write().unwrap().add_root("root");
+
+        let writers: Vec<_> = (0..4)
+            .map(|worker| {
+                let tree = Arc::clone(&tree);
+                thread::spawn(move || {
+                    for i in 0..25 {
+                        let mut tree = tree.write().unwrap();
+                        let id = tree.add_child(root, format!("{}-{}", worker, i)).unwrap();
+                        tree.toggle(id);
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let tree = tree.read().unwrap();
+        assert_eq!(tree.node_count(), 101);
+        let root_ref = tree.get(root).unwrap();
+        assert_eq!(read(&root_ref).children.len(), 100);
+        // Every child is done, so the root is too
+        assert!(read(&root_ref).done);
+    }
+
+    #[test]
+    fn toggle_matches_tree_under_each_policy() {
+        use crate::tree::Tree;
+
+        for policy in [DonePolicy::AllChildren, DonePolicy::AnyChild] {
+            let mut tree: Tree = Tree::new();
+            let mut sync_tree: SyncTree = SyncTree::new();
+            tree.set_done_policy(policy);
+            sync_tree.set_done_policy(policy);
+
+            // root > (a > (a1, a2), b)
+            let root = tree.add_root("root");
+            assert_eq!(sync_tree.add_root("root"), root);
+            for (parent, title) in [(root, "a"), (root, "b"), (root + 1, "a1"), (root + 1, "a2")] {
+                let id = tree.add_child(parent, title).unwrap();
+                assert_eq!(sync_tree.add_child(parent, title).unwrap(), id);
+            }
+
+            // Leaves, a parent with pending children, then the leaves again
+            let (a, b, a1, a2) = (root + 1, root + 2, root + 3, root + 4);
+            for id in [a1, a, b, a2, a1, root, a2] {
+                assert!(tree.toggle(id));
+                assert!(sync_tree.toggle(id));
+
+                for id in [root, a, b, a1, a2] {
+                    assert_eq!(
+                        read(&sync_tree.get(id).unwrap()).done,
+                        tree.get(id).unwrap().borrow().done,
+                        "node {} under {:?}",
+                        id,
+                        policy
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn node_handles_are_read_from_other_threads() {
+        let mut tree: SyncTree = SyncTree::new();
+        let root = tree.add_root("root");
+        let child = tree.add_child(root, "child").unwrap();
+        let handle = tree.get(child).unwrap();
+
+        let title = thread::spawn(move || read(&handle).title.clone())
+            .join()
+            .unwrap();
+        assert_eq!(title, "child");
+    }
+}
@@ -1,301 +1,4498 @@
 // src/tree.rs
 
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsString;
 use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
 use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicU32, Ordering as AtomicOrdering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::date::Date;
+use crate::json::{self, Value};
+use crate::xml;
+use regex::Regex;
 
 /// Shared reference to a node: multiple owners, interior mutability.
-pub type NodeRef = Rc<RefCell<Node>>;
+pub type NodeRef<T = ()> = Rc<RefCell<Node<T>>>;
+
+/// Title of the root node that archived branches are moved under.
+pub const ARCHIVE_TITLE: &str = "Archive";
+
+/// ANSI escapes used by `Tree::fmt_colored`
+const ANSI_GREEN: &str = "\x1b[32m";
+const ANSI_RESET: &str = "\x1b[0m";
 
 #[derive(Debug)]
-pub struct Node {
+pub struct Node<T = ()> {
     pub id: u32,
     pub title: String,
     pub done: bool,
-    pub children: Vec<NodeRef>,              // Child nodes of this node
-    pub parent: Option<Weak<RefCell<Node>>>, // Weak pointer to the node
+    pub priority: u8, // 0 = normal; higher is more urgent
+    pub weight: u32,  // Relative size for `Tree::weighted_progress`; 1 by default
+    pub tags: Vec<String>,
+    pub completed_at: Option<SystemTime>, // When `done` last became true
+    pub due: Option<Date>,
+    pub created_at: SystemTime,
+    pub modified_at: SystemTime,   // Last title, done or position change
+    pub data: T,                   // Caller-defined payload; `()` for plain todo trees
+    pub children: Vec<NodeRef<T>>, // Child nodes of this node
+    pub parent: Option<Weak<RefCell<Node<T>>>>, // Weak pointer to the node
+}
+
+impl<T: Default> Node<T> {
+    pub fn new(
+        id: u32,
+        title: impl Into<String>,
+        parent: Option<Weak<RefCell<Node<T>>>>,
+    ) -> NodeRef<T> {
+        Node::with_data(id, title, T::default(), parent)
+    }
 }
 
-impl Node {
-    pub fn new(id: u32, title: impl Into<String>, parent: Option<Weak<RefCell<Node>>>) -> NodeRef {
+impl<T> Node<T> {
+    /// Like `new`, with an explicit payload
+    pub fn with_data(
+        id: u32,
+        title: impl Into<String>,
+        data: T,
+        parent: Option<Weak<RefCell<Node<T>>>>,
+    ) -> NodeRef<T> {
+        let now = SystemTime::now();
         Rc::new(RefCell::new(Node {
             id,
             title: title.into(),
             done: false,
+            priority: 0,
+            weight: 1,
+            tags: Vec::new(),
+            completed_at: None,
+            due: None,
+            created_at: now,
+            modified_at: now,
+            data,
             children: Vec::new(),
-            parent: parent,
+            parent,
         }))
     }
-}
 
-pub struct Tree {
-    roots: Vec<NodeRef>,
-    index: HashMap<u32, NodeRef>,
-    next_id: u32,
-}
+    /// Set `done`, stamping `completed_at` when it becomes true and clearing
+    /// it when it becomes false. Returns `true` if the flag changed (and
+    /// only then bumps `modified_at`).
+    pub fn set_done(&mut self, done: bool) -> bool {
+        if self.done == done {
+            return false;
+        }
+
+        let now = SystemTime::now();
+        self.done = done;
+        self.completed_at = if done { Some(now) } else { None };
+        self.modified_at = now;
+        true
+    }
+
+    /// Record that the node changed just now
+    pub fn touch(&mut self) {
+        self.modified_at = SystemTime::now();
+    }
+}
+
+/// Drop children iteratively; the default drop glue recurses once per level
+/// and overflows the stack on very deep chains.
+impl<T> Drop for Node<T> {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(child) = stack.pop() {
+            // Only unwrap nodes nobody else holds; shared ones drop later
+            if let Ok(cell) = Rc::try_unwrap(child) {
+                stack.append(&mut cell.borrow_mut().children);
+            }
+        }
+    }
+}
+
+/// How a parent's `done` flag is derived from its children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DonePolicy {
+    /// Parent is done when every child is done
+    #[default]
+    AllChildren,
+    /// Parent is done when at least one child is done
+    AnyChild,
+    /// Parents are never changed automatically
+    Manual,
+}
+
+/// How `Tree::fmt_styled` draws nesting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayStyle {
+    /// Two spaces per level, as in `Display`
+    #[default]
+    Plain,
+    /// `├──` / `└──` connectors with `│` guide lines
+    Unicode,
+}
+
+/// Knobs shared by the pretty printers; the default is plain `Display`.
+#[derive(Clone, Copy, Default)]
+struct RenderOptions<'a> {
+    max_depth: Option<usize>, // Levels below the start node (None = all)
+    style: DisplayStyle,      // How nesting is drawn
+    color: bool,              // ANSI green for done nodes
+    visible: Option<&'a HashSet<u32>>, // Only these ids (None = every node)
+}
+
+impl RenderOptions<'_> {
+    fn shows(&self, id: u32) -> bool {
+        self.visible.is_none_or(|visible| visible.contains(&id))
+    }
+}
+
+/// Casing rules for `Tree::apply_title_case`.
+///
+/// These are deliberately simple: `Sentence` and `Title` lowercase everything
+/// they don't capitalize, so acronyms like "API" become "Api"/"api".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TitleCaseMode {
+    /// First letter uppercase, the rest lowercase
+    Sentence,
+    /// First letter of every whitespace-separated word uppercase, the rest lowercase
+    Title,
+    Upper,
+    Lower,
+}
+
+impl TitleCaseMode {
+    /// Apply this rule to one title (Unicode-aware via `to_uppercase`/`to_lowercase`)
+    pub fn apply(self, title: &str) -> String {
+        match self {
+            TitleCaseMode::Upper => title.to_uppercase(),
+            TitleCaseMode::Lower => title.to_lowercase(),
+            TitleCaseMode::Sentence | TitleCaseMode::Title => {
+                let mut out = String::with_capacity(title.len());
+                let mut at_start = true;
+                for c in title.chars() {
+                    if at_start && !c.is_whitespace() {
+                        out.extend(c.to_uppercase());
+                        at_start = false;
+                    } else {
+                        out.extend(c.to_lowercase());
+                        if c.is_whitespace() && self == TitleCaseMode::Title {
+                            at_start = true;
+                        }
+                    }
+                }
+                out
+            }
+        }
+    }
+}
+
+/// Default number of edits kept for `Tree::undo`.
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// A place in the forest: the parent id (`None` for roots) and the index
+/// among that parent's children.
+type Slot = (Option<u32>, usize);
+
+/// A recorded mutation, as kept on the undo/redo stacks.
+enum Edit<T> {
+    /// `node` was created at `slot` (`add_root`, `add_child`)
+    Added { node: NodeRef<T>, slot: Slot },
+    /// `node` and its subtree were detached from `slot` (`delete`);
+    /// `titles` holds the subtree's stashed pre-rename titles
+    Deleted {
+        node: NodeRef<T>,
+        slot: Slot,
+        titles: Vec<(u32, String)>,
+    },
+    /// A node moved between slots (`move_node`)
+    Moved { id: u32, from: Slot, to: Slot },
+    /// A node was toggled; `before` holds the prior done flags of the node
+    /// and every ancestor, since propagation may have changed them.
+    /// `local` toggles (`toggle_local`) skipped propagation.
+    Toggled {
+        id: u32,
+        local: bool,
+        before: Vec<(u32, bool)>,
+    },
+    /// A whole subtree was marked `done`; `before` holds the prior flags of
+    /// the subtree and every ancestor
+    SubtreeDone {
+        id: u32,
+        done: bool,
+        before: Vec<(u32, bool)>,
+    },
+    /// The children of `parent` (or the roots) went from the id order
+    /// `before` to `after` (sorts, `reverse_children`, `move_up`/`move_down`)
+    Reordered {
+        parent: Option<u32>,
+        before: Vec<u32>,
+        after: Vec<u32>,
+    },
+    /// Two nodes traded places (`swap`); swapping again reverts it
+    Swapped { a: u32, b: u32 },
+}
+
+/// A change reported to `Tree::on_change` listeners.
+///
+/// Operations that add, remove or move a whole subtree report only its top
+/// node. Done flags changed as a side effect (propagation, subtree marking,
+/// undo) arrive as `DoneChanged`, as they happen, so they may come before
+/// the structural event of the operation that caused them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeEvent {
+    /// A node (with any subtree) now sits under `parent`, or is a root
+    Added { id: u32, parent: Option<u32> },
+    /// A node and its subtree left the tree
+    Removed { id: u32 },
+    /// A node's own flag was flipped by `toggle` or `toggle_local`
+    Toggled { id: u32, done: bool },
+    /// A node was re-parented or reordered among its siblings
+    Moved { id: u32, new_parent: Option<u32> },
+    /// A node's done flag changed without it being toggled directly
+    DoneChanged { id: u32, done: bool },
+}
+
+/// A callback registered with `Tree::on_change`.
+pub type Listener = Box<dyn FnMut(TreeEvent)>;
+
+/// One node in the canonical flattened form of a tree (see `Tree::to_flat_records`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatRecord {
+    pub id: u32,
+    pub parent_id: Option<u32>,
+    pub depth: usize, // Roots are depth 0; redundant with `parent_id`, kept for convenience
+    pub done: bool,
+    pub title: String,
+}
+
+/// Plain, owned form of a node and its subtree, for handing the tree to
+/// serde (with the `serde` feature) or any other external format.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SerializableNode {
+    pub id: u32,
+    pub title: String,
+    pub done: bool,
+    pub children: Vec<SerializableNode>,
+}
+
+/// Drop children iteratively, like `Node`
+impl Drop for SerializableNode {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(mut child) = stack.pop() {
+            stack.append(&mut child.children);
+        }
+    }
+}
+
+/// Nested description of a node and its subtree, for building a tree in one
+/// go without juggling ids, e.g.
+/// `TreeBuilder::node("Project").child(TreeBuilder::node("Design").done())`.
+/// Turn it into a tree with `Tree::from_builder`.
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeBuilder {
+    title: String,
+    done: bool,
+    children: Vec<TreeBuilder>,
+}
+
+#[allow(dead_code)]
+impl TreeBuilder {
+    /// A pending node with no children yet
+    pub fn node(title: impl Into<String>) -> Self {
+        TreeBuilder {
+            title: title.into(),
+            done: false,
+            children: Vec::new(),
+        }
+    }
+
+    /// Append a child (after any added before it)
+    pub fn child(mut self, child: TreeBuilder) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Append several children in order
+    pub fn children(mut self, children: impl IntoIterator<Item = TreeBuilder>) -> Self {
+        self.children.extend(children);
+        self
+    }
+
+    /// Mark this node done
+    pub fn done(mut self) -> Self {
+        self.done = true;
+        self
+    }
+}
+
+/// Why a tree mutation was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeError {
+    /// No node has this id
+    NotFound(u32),
+    /// The new parent is inside the subtree being moved
+    WouldCreateCycle,
+    /// A node can't be moved under itself
+    SelfMove,
+    /// Titles must contain something besides whitespace
+    EmptyTitle,
+    /// `parent` already has the `max_children` cap of `max` children
+    ChildLimit { parent: u32, max: usize },
+    /// An imported tree used the same id twice
+    DuplicateId(u32),
+    /// No node sits at this title path (`/Root/Child/...`)
+    PathNotFound(String),
+    /// Two or more siblings along this title path share the same title
+    AmbiguousPath(String),
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeError::NotFound(id) => write!(f, "id {} not found", id),
+            TreeError::WouldCreateCycle => write!(f, "move would create a cycle"),
+            TreeError::SelfMove => write!(f, "cannot move a node under itself"),
+            TreeError::EmptyTitle => write!(f, "title cannot be empty"),
+            TreeError::ChildLimit { parent, max } => write!(
+                f,
+                "parent {} already has the maximum of {} children",
+                parent, max
+            ),
+            TreeError::DuplicateId(id) => write!(f, "duplicate id {}", id),
+            TreeError::PathNotFound(path) => write!(f, "no node at path {}", path),
+            TreeError::AmbiguousPath(path) => {
+                write!(
+                    f,
+                    "path {} is ambiguous (several nodes share that title)",
+                    path
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TreeError {}
+
+/// Whole-tree counts, as returned by `Tree::stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TreeStats {
+    pub total_nodes: usize,
+    pub done_nodes: usize,
+    pub leaf_count: usize,
+    pub root_count: usize,
+    pub max_depth: usize, // Depth of the deepest node, roots being 0 (as in `Tree::depth`)
+}
+
+/// A forest of titled nodes. `T` is an optional per-node payload (`Node::data`);
+/// the tree logic never looks at it.
+pub struct Tree<T = ()> {
+    roots: Vec<NodeRef<T>>,
+    index: HashMap<u32, NodeRef<T>>,
+    next_id: u32,
+    highlighted: Option<u32>, // Node whose root path is marked in `show`
+    done_policy: DonePolicy,
+    in_batch: bool,                    // Suppresses done propagation until `end_batch`
+    last_titles: HashMap<u32, String>, // Title before the latest rename, per node
+    max_children: Option<usize>,       // Cap on direct children per node; `None` = unlimited
+    undo_stack: VecDeque<Edit<T>>,     // Oldest edit at the front
+    redo_stack: Vec<Edit<T>>,
+    history_limit: usize,              // Max edits kept on each stack
+    listeners: RefCell<Vec<Listener>>, // `RefCell` so propagation (`&self`) can fire events
+}
+
+impl Tree {
+    /// Create an empty tree. Trees with a payload start from `Tree::default()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<T> Default for Tree<T> {
+    fn default() -> Self {
+        Tree {
+            roots: Vec::new(),
+            index: HashMap::new(),
+            next_id: 1,
+            highlighted: None,
+            done_policy: DonePolicy::default(),
+            in_batch: false,
+            last_titles: HashMap::new(),
+            max_children: None,
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            listeners: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<T> Tree<T> {
+    /// Create an empty tree whose ids start at `start`, for interop with an
+    /// external system that owns the lower part of the id space.
+    pub fn with_starting_id(start: u32) -> Self {
+        Tree {
+            next_id: start,
+            ..Tree::default()
+        }
+    }
+
+    /// Hand out a fresh id without creating a node, so external code can
+    /// pre-allocate. The id will never be used by this tree.
+    pub fn reserve_id(&mut self) -> u32 {
+        self.alloc_id()
+    }
+
+    /// Create an empty tree in which no node may have more than `max`
+    /// direct children.
+    pub fn with_max_children(max: usize) -> Self {
+        Tree {
+            max_children: Some(max),
+            ..Tree::default()
+        }
+    }
+
+    /// The per-node cap on direct children, if any
+    pub fn max_children(&self) -> Option<usize> {
+        self.max_children
+    }
+
+    /// Set or clear (`None`) the per-node cap on direct children.
+    ///
+    /// Only checked when children are added; nodes already over a new cap keep
+    /// their children.
+    pub fn set_max_children(&mut self, max: Option<usize>) {
+        self.max_children = max;
+    }
+
+    /// Return `true` if `id` exists and cannot take another child under
+    /// the current `max_children` cap.
+    pub fn is_at_child_limit(&self, id: u32) -> bool {
+        self.index
+            .get(&id)
+            .is_some_and(|node_ref| !self.has_room_for_child(node_ref))
+    }
+
+    /// The rule used to roll completion up to parents
+    pub fn done_policy(&self) -> DonePolicy {
+        self.done_policy
+    }
+
+    /// Change the completion rollup rule.
+    ///
+    /// Takes effect on the next mutation; existing flags are not recomputed.
+    pub fn set_done_policy(&mut self, policy: DonePolicy) {
+        self.done_policy = policy;
+    }
+
+    /// Start a batch: mutations skip done propagation until `end_batch`.
+    ///
+    /// Useful for many moves/toggles in a row, which would otherwise each
+    /// rescan their ancestors.
+    pub fn begin_batch(&mut self) {
+        self.in_batch = true;
+    }
+
+    /// Close the batch and recompute every done flag once.
+    /// Returns `false` if no batch was open.
+    pub fn end_batch(&mut self) -> bool {
+        if !self.in_batch {
+            return false;
+        }
+
+        self.in_batch = false;
+        self.recompute_all_done();
+        true
+    }
+
+    /// Whether a batch is currently open
+    pub fn in_batch(&self) -> bool {
+        self.in_batch
+    }
+
+    /// Recompute the done flag of every parent node from its children,
+    /// bottom-up, per the active policy.
+    ///
+    /// On a consistent tree this gives the same result as propagating after
+    /// every individual change.
+    pub fn recompute_all_done(&mut self) {
+        // Reverse pre-order visits every descendant before its ancestors
+        for node_ref in self.preorder().iter().rev() {
+            self.rollup_done(node_ref);
+        }
+    }
+
+    /// Recompute one node's `done` from its children per the active policy,
+    /// then its ancestors. Returns the node's resulting flag, or `None` if not found.
+    ///
+    /// A targeted repair for a parent that disagrees with its children; unlike
+    /// normal propagation it also runs while a batch is open.
+    pub fn fix_node_done(&mut self, id: u32) -> Option<bool> {
+        let node_ref = self.index.get(&id).cloned()?;
+
+        let mut current = Some(Rc::clone(&node_ref));
+        while let Some(current_ref) = current {
+            self.rollup_done(&current_ref);
+            current = current_ref
+                .borrow()
+                .parent
+                .as_ref()
+                .and_then(|parent| parent.upgrade());
+        }
+
+        let done = node_ref.borrow().done;
+        Some(done)
+    }
+
+    /// Add a new root node. Returns its ID.
+    pub fn add_root(&mut self, title: impl Into<String>) -> u32
+    where
+        T: Default,
+    {
+        self.add_root_with(title, T::default())
+    }
+
+    /// Like `add_root`, with an explicit payload
+    pub fn add_root_with(&mut self, title: impl Into<String>, data: T) -> u32 {
+        let id = self.alloc_id();
+        let node = Node::with_data(id, title, data, None);
+
+        self.index.insert(id, Rc::clone(&node));
+        self.roots.push(Rc::clone(&node));
+
+        let slot = (None, self.roots.len() - 1);
+        self.record(Edit::Added { node, slot });
+
+        id
+    }
+
+    /// Add a child under `parent_id`. Returns child ID on success
+    ///
+    /// Fails if the title is blank, or the parent is missing or already at
+    /// the `max_children` cap.
+    pub fn add_child(&mut self, parent_id: u32, title: impl Into<String>) -> Result<u32, TreeError>
+    where
+        T: Default,
+    {
+        self.add_child_with(parent_id, title, T::default())
+    }
+
+    /// Like `add_child`, with an explicit payload
+    pub fn add_child_with(
+        &mut self,
+        parent_id: u32,
+        title: impl Into<String>,
+        data: T,
+    ) -> Result<u32, TreeError> {
+        let title = title.into();
+        if title.trim().is_empty() {
+            return Err(TreeError::EmptyTitle);
+        }
+
+        let parent = self.node_or_err(parent_id)?;
+        self.check_room_for_child(&parent)?;
+
+        let id = self.alloc_id();
+        let parent_weak = Rc::downgrade(&parent);
+        let child = Node::with_data(id, title, data, Some(parent_weak));
+
+        parent.borrow_mut().children.push(child.clone());
+        self.index.insert(id, Rc::clone(&child));
+
+        // recompute done flags upward from parent
+        self.propagate_done_upward(&parent);
+
+        let slot = (Some(parent_id), parent.borrow().children.len() - 1);
+        self.record(Edit::Added { node: child, slot });
+
+        Ok(id)
+    }
+
+    /// Find-or-create the chain `root_title > segments...`, matching titles exactly.
+    ///
+    /// Returns the id of the final node and the ids of nodes that had to be
+    /// created (empty if the whole path already existed). When several
+    /// siblings share a title, the first one is followed.
+    ///
+    /// Fails with `TreeError::ChildLimit` if a segment can't be created
+    /// because its parent is at the `max_children` cap (segments created
+    /// before that are kept), or `EmptyTitle` for a blank root title or
+    /// segment, checked before anything is created.
+    pub fn ensure_path(
+        &mut self,
+        root_title: &str,
+        segments: &[&str],
+    ) -> Result<(u32, Vec<u32>), TreeError>
+    where
+        T: Default,
+    {
+        if std::iter::once(&root_title)
+            .chain(segments)
+            .any(|title| title.trim().is_empty())
+        {
+            return Err(TreeError::EmptyTitle);
+        }
+
+        let mut created = Vec::new();
+
+        let existing_root = self
+            .roots
+            .iter()
+            .find(|root_ref| root_ref.borrow().title == root_title)
+            .map(|root_ref| root_ref.borrow().id);
+        let mut current = match existing_root {
+            Some(id) => id,
+            None => {
+                let id = self.add_root(root_title);
+                created.push(id);
+                id
+            }
+        };
+
+        for segment in segments {
+            let existing_child = {
+                let node = self.index[&current].borrow();
+                node.children
+                    .iter()
+                    .find(|child_ref| child_ref.borrow().title == *segment)
+                    .map(|child_ref| child_ref.borrow().id)
+            };
+            current = match existing_child {
+                Some(id) => id,
+                None => {
+                    let id = self.add_child(current, *segment)?;
+                    created.push(id);
+                    id
+                }
+            };
+        }
+
+        Ok((current, created))
+    }
+
+    /// Toggle the `done` flag for a node. Returns `true` if found.
+    ///
+    /// After toggling, completion status is propagated upwards according to
+    /// the active `DonePolicy` (by default a parent becomes done if *all* its
+    /// children are done).
+    pub fn toggle(&mut self, id: u32) -> bool {
+        self.toggle_get(id).is_some()
+    }
+
+    /// Like `toggle`, but returns the node's resulting `done` value
+    /// (after propagation), or `None` if not found.
+    pub fn toggle_get(&mut self, id: u32) -> Option<bool> {
+        let before = self
+            .lineage(id)?
+            .iter()
+            .map(|node_ref| {
+                let node = node_ref.borrow();
+                (node.id, node.done)
+            })
+            .collect();
+
+        let done = self.apply_toggle(id, false)?;
+        self.record(Edit::Toggled {
+            id,
+            local: false,
+            before,
+        });
+
+        Some(done)
+    }
+
+    /// Flip a node's `done` flag without propagating to its ancestors, e.g.
+    /// to mark a parent done while deferring one of its subtasks. Returns
+    /// `false` if not found.
+    ///
+    /// The manual flag is not sticky: the next change to any child (toggle,
+    /// add, delete, move) re-runs `propagate_done_upward` from there, which
+    /// recomputes this node from its children under the active policy and
+    /// may overwrite it.
+    pub fn toggle_local(&mut self, id: u32) -> bool {
+        let Some(node_ref) = self.index.get(&id) else {
+            return false;
+        };
+        let before = vec![(id, node_ref.borrow().done)];
+
+        self.apply_toggle(id, true);
+        self.record(Edit::Toggled {
+            id,
+            local: true,
+            before,
+        });
+        true
+    }
+
+    /// Mark a node and every descendant done, then propagate upwards.
+    /// Returns `false` if not found.
+    pub fn complete_subtree(&mut self, id: u32) -> bool {
+        self.set_subtree_done(id, true)
+    }
+
+    /// Mark a node and every descendant not done, then propagate upwards.
+    /// Returns `false` if not found.
+    pub fn uncomplete_subtree(&mut self, id: u32) -> bool {
+        self.set_subtree_done(id, false)
+    }
+
+    fn set_subtree_done(&mut self, id: u32, done: bool) -> bool {
+        let Some(node_ref) = self.index.get(&id).cloned() else {
+            return false;
+        };
+
+        // Ancestors may change through propagation, so save them too
+        let mut affected = Self::subtree_preorder(&node_ref);
+        if let Some(lineage) = self.lineage(id) {
+            affected.extend(lineage.into_iter().rev().skip(1));
+        }
+        let before = affected
+            .iter()
+            .map(|node_ref| {
+                let node = node_ref.borrow();
+                (node.id, node.done)
+            })
+            .collect();
+
+        self.apply_subtree_done(&node_ref, done);
+        self.record(Edit::SubtreeDone { id, done, before });
+
+        true
+    }
+
+    /// Set or clear a node's due date. Returns `false` if not found.
+    pub fn set_due(&mut self, id: u32, due: Option<Date>) -> bool {
+        let Some(node_ref) = self.index.get(&id) else {
+            return false;
+        };
+
+        let mut node = node_ref.borrow_mut();
+        node.due = due;
+        node.touch();
+        true
+    }
+
+    /// Set a node's priority (0 = normal). Returns `false` if not found.
+    pub fn set_priority(&mut self, id: u32, level: u8) -> bool {
+        let Some(node_ref) = self.index.get(&id) else {
+            return false;
+        };
+
+        let mut node = node_ref.borrow_mut();
+        node.priority = level;
+        node.touch();
+        true
+    }
+
+    /// Set a node's weight for `weighted_progress`. Returns `false` if not
+    /// found.
+    pub fn set_weight(&mut self, id: u32, weight: u32) -> bool {
+        let Some(node_ref) = self.index.get(&id) else {
+            return false;
+        };
+
+        let mut node = node_ref.borrow_mut();
+        node.weight = weight;
+        node.touch();
+        true
+    }
+
+    /// Replace a node's title. Fails if not found or the title is blank.
+    ///
+    /// The previous title is kept so `rename_undo` can restore it; a second
+    /// rename overwrites it (one level of undo per node).
+    pub fn rename(&mut self, id: u32, new_title: impl Into<String>) -> Result<(), TreeError> {
+        let new_title = new_title.into();
+        if new_title.trim().is_empty() {
+            return Err(TreeError::EmptyTitle);
+        }
+
+        let node_ref = self.node_or_err(id)?;
+        let old_title = {
+            let mut node = node_ref.borrow_mut();
+            node.touch();
+            std::mem::replace(&mut node.title, new_title)
+        };
+        self.last_titles.insert(id, old_title);
+        Ok(())
+    }
+
+    /// Restore the title a node had before its latest `rename`.
+    /// Returns the restored title, or `None` if there is nothing to undo.
+    pub fn rename_undo(&mut self, id: u32) -> Option<String> {
+        let node_ref = self.index.get(&id)?;
+        let old_title = self.last_titles.remove(&id)?;
+
+        let mut node = node_ref.borrow_mut();
+        node.title = old_title.clone();
+        node.touch();
+        Some(old_title)
+    }
+
+    /// Re-case every title with `mode`, in tree order, touching each node
+    /// whose title changes. Returns how many titles changed.
+    pub fn apply_title_case(&mut self, mode: TitleCaseMode) -> usize {
+        let mut changed = 0;
+        for node_ref in self.iter_dfs() {
+            let mut node = node_ref.borrow_mut();
+            let new_title = mode.apply(&node.title);
+            if new_title != node.title {
+                node.title = new_title;
+                node.touch();
+                changed += 1;
+            }
+        }
+
+        changed
+    }
+
+    /// Delete a node and its subtree. Fails if not found.
+    ///
+    /// - Detaches it from parent or roots
+    /// - Removes it and all descendants from the index
+    /// - Recomputes parent completion upwards
+    pub fn delete(&mut self, id: u32) -> Result<(), TreeError> {
+        let node_ref = self.node_or_err(id)?;
+
+        // 1. Detach from the parent or from roots
+        let Some(slot) = self.detach(&node_ref) else {
+            return Err(TreeError::NotFound(id));
+        };
+
+        // 2. Remove from index (this node + all descendants)
+        let titles = self.remove_from_index_rec(&node_ref);
+
+        self.record(Edit::Deleted {
+            node: node_ref,
+            slot,
+            titles,
+        });
+        Ok(())
+    }
+
+    /// Remove every node and start ids again from 1. Settings (done policy,
+    /// child cap, history limit) are kept; undo history is dropped, since
+    /// its edits refer to the removed nodes.
+    ///
+    /// Parent links are `Weak`, so dropping the roots and the index frees
+    /// every node.
+    pub fn clear(&mut self) {
+        for root in std::mem::take(&mut self.roots) {
+            let id = root.borrow().id;
+            self.emit(TreeEvent::Removed { id });
+        }
+        self.index.clear();
+        self.next_id = 1;
+        self.highlighted = None;
+        self.last_titles.clear();
+        self.clear_history();
+    }
+
+    /// Move a node to a new parent, as its last child
+    ///
+    /// - Fails if `id == new_parent_id`
+    /// - Fails if either id is missing
+    /// - Fails if `new_parent` is in the subtree of `id` (would create a cycle)
+    /// - Fails if `new_parent` is already at the `max_children` cap
+    pub fn move_node(&mut self, id: u32, new_parent_id: u32) -> Result<(), TreeError> {
+        self.move_node_at(id, new_parent_id, usize::MAX)
+    }
+
+    /// Like `move_node`, but insert at `index` among the new parent's
+    /// children (clamped to their count) instead of appending.
+    pub fn move_node_at(
+        &mut self,
+        id: u32,
+        new_parent_id: u32,
+        index: usize,
+    ) -> Result<(), TreeError> {
+        if id == new_parent_id {
+            return Err(TreeError::SelfMove);
+        }
+
+        let node_ref = self.node_or_err(id)?;
+        let new_parent = self.node_or_err(new_parent_id)?;
+
+        if Self::is_descendant(&node_ref, &new_parent) {
+            return Err(TreeError::WouldCreateCycle);
+        }
+
+        // Moving within the same parent doesn't add a child
+        let same_parent = node_ref
+            .borrow()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .is_some_and(|parent| Rc::ptr_eq(&parent, &new_parent));
+        if !same_parent {
+            self.check_room_for_child(&new_parent)?;
+        }
+
+        // 1. Detach from old parent or roots
+        let Some(from) = self.detach(&node_ref) else {
+            return Err(TreeError::NotFound(id));
+        };
+
+        // 2. Attach to new parent, recomputing completion upwards from it
+        let position = self.attach_at(&node_ref, Some(&new_parent), index);
+        node_ref.borrow_mut().touch();
+
+        self.record(Edit::Moved {
+            id,
+            from,
+            to: (Some(new_parent_id), position),
+        });
+        Ok(())
+    }
+
+    /// Deep-copy the subtree at `id` as the last child of `new_parent_id`,
+    /// giving every copy a fresh id. Returns the id of the copied root.
+    /// Fails if either id is missing or the new parent is at the
+    /// `max_children` cap.
+    pub fn duplicate(&mut self, id: u32, new_parent_id: u32) -> Result<u32, TreeError>
+    where
+        T: Clone,
+    {
+        let source = self.node_or_err(id)?;
+        let new_parent = self.node_or_err(new_parent_id)?;
+        self.check_room_for_child(&new_parent)?;
+
+        let copy = Self::copy_subtree(&source, |_| self.alloc_id());
+        // The copies are new nodes as far as auditing goes
+        let now = SystemTime::now();
+        for node_ref in Self::subtree_preorder(&copy) {
+            let mut node = node_ref.borrow_mut();
+            node.created_at = now;
+            node.modified_at = now;
+        }
+        let position = self.attach_at(&copy, Some(&new_parent), usize::MAX);
+        self.index_subtree(&copy);
+
+        let copy_id = copy.borrow().id;
+        self.record(Edit::Added {
+            node: copy,
+            slot: (Some(new_parent_id), position),
+        });
+
+        Ok(copy_id)
+    }
+
+    /// Append all of `other`'s roots as new roots of this tree, structure and
+    /// done flags intact. Every merged node gets a fresh id (in pre-order);
+    /// returns the new ids of the merged roots.
+    ///
+    /// Fails with `ChildLimit` (naming the parent by its id in `other`) if
+    /// any incoming node has more children than `max_children` allows; the
+    /// tree is left untouched.
+    pub fn merge(&mut self, other: Tree<T>) -> Result<Vec<u32>, TreeError> {
+        if let Some(max) = self.max_children {
+            for root in &other.roots {
+                for node_ref in Self::subtree_preorder(root) {
+                    let node = node_ref.borrow();
+                    if node.children.len() > max {
+                        return Err(TreeError::ChildLimit {
+                            parent: node.id,
+                            max,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut new_root_ids = Vec::with_capacity(other.roots.len());
+
+        // `other` is consumed, so its nodes can be re-labelled and adopted as-is
+        for root in other.roots {
+            for node_ref in Self::subtree_preorder(&root) {
+                node_ref.borrow_mut().id = self.alloc_id();
+            }
+
+            let position = self.attach_at(&root, None, usize::MAX);
+            self.index_subtree(&root);
+
+            let id = root.borrow().id;
+            new_root_ids.push(id);
+            self.record(Edit::Added {
+                node: root,
+                slot: (None, position),
+            });
+        }
+
+        Ok(new_root_ids)
+    }
+
+    /// Sort a node's children by title (case-insensitive) when `by_title`,
+    /// otherwise by id. The sort is stable and only reorders `children`.
+    /// Returns `false` if not found.
+    pub fn sort_children(&mut self, id: u32, by_title: bool) -> bool {
+        let Some(node_ref) = self.index.get(&id).cloned() else {
+            return false;
+        };
+
+        let before = self.child_order(Some(id));
+        Self::sort_nodes(&mut node_ref.borrow_mut().children, by_title);
+        self.emit_reordered(Some(id));
+        self.record_reorder(Some(id), before);
+        true
+    }
+
+    /// Order a node's children by descending priority, breaking ties by id.
+    /// Returns `false` if not found.
+    pub fn sort_children_by_priority(&mut self, id: u32) -> bool {
+        let Some(node_ref) = self.index.get(&id).cloned() else {
+            return false;
+        };
+
+        let before = self.child_order(Some(id));
+        node_ref.borrow_mut().children.sort_by_key(|child_ref| {
+            let child = child_ref.borrow();
+            (std::cmp::Reverse(child.priority), child.id)
+        });
+        self.emit_reordered(Some(id));
+        self.record_reorder(Some(id), before);
+        true
+    }
+
+    /// Sort the root list, like `sort_children`
+    pub fn sort_roots(&mut self, by_title: bool) {
+        let before = self.child_order(None);
+        Self::sort_nodes(&mut self.roots, by_title);
+        self.emit_reordered(None);
+        self.record_reorder(None, before);
+    }
+
+    /// Reverse the order of a node's children, or of the roots when `id` is
+    /// `None`. Only the order changes. Returns `false` if not found.
+    pub fn reverse_children(&mut self, id: Option<u32>) -> bool {
+        let before = self.child_order(id);
+        match id {
+            Some(id) => {
+                let Some(node_ref) = self.index.get(&id) else {
+                    return false;
+                };
+                node_ref.borrow_mut().children.reverse();
+            }
+            None => self.roots.reverse(),
+        }
+
+        self.emit_reordered(id);
+        self.record_reorder(id, before);
+        true
+    }
+
+    /// Swap a node with its previous sibling. Returns `false` if it is
+    /// already first or not found.
+    pub fn move_up(&mut self, id: u32) -> bool {
+        self.swap_with_sibling(id, false)
+    }
+
+    /// Swap a node with its next sibling. Returns `false` if it is already
+    /// last or not found.
+    pub fn move_down(&mut self, id: u32) -> bool {
+        self.swap_with_sibling(id, true)
+    }
+
+    /// Exchange the positions of two nodes (with their subtrees): each takes
+    /// the other's parent and index. Returns `false` if either is missing,
+    /// they are the same node, or one contains the other. Child counts don't
+    /// change, so the `max_children` cap can't be exceeded.
+    pub fn swap(&mut self, a: u32, b: u32) -> bool {
+        if !self.apply_swap(a, b) {
+            return false;
+        }
+
+        self.record(Edit::Swapped { a, b });
+        true
+    }
+
+    /// `swap` without recording it
+    fn apply_swap(&mut self, a: u32, b: u32) -> bool {
+        if a == b {
+            return false;
+        }
+        let (Some(a_ref), Some(b_ref)) = (self.get(a), self.get(b)) else {
+            return false;
+        };
+        if Self::is_descendant(&a_ref, &b_ref) || Self::is_descendant(&b_ref, &a_ref) {
+            return false;
+        }
+        let (Some(a_pos), Some(b_pos)) = (self.sibling_position(a), self.sibling_position(b))
+        else {
+            return false;
+        };
+
+        let a_parent = a_ref.borrow().parent.as_ref().and_then(Weak::upgrade);
+        let b_parent = b_ref.borrow().parent.as_ref().and_then(Weak::upgrade);
+        for (parent, pos, node_ref) in [(&a_parent, a_pos, &b_ref), (&b_parent, b_pos, &a_ref)] {
+            match parent {
+                Some(parent) => parent.borrow_mut().children[pos] = Rc::clone(node_ref),
+                None => self.roots[pos] = Rc::clone(node_ref),
+            }
+        }
+        a_ref.borrow_mut().parent = b_parent.as_ref().map(Rc::downgrade);
+        b_ref.borrow_mut().parent = a_parent.as_ref().map(Rc::downgrade);
+
+        a_ref.borrow_mut().touch();
+        b_ref.borrow_mut().touch();
+        // Each parent now holds a different subtree
+        for parent in [&a_parent, &b_parent].into_iter().flatten() {
+            self.propagate_done_upward(parent);
+        }
+
+        let parent_id = |parent: &Option<NodeRef<T>>| parent.as_ref().map(|p| p.borrow().id);
+        self.emit(TreeEvent::Moved {
+            id: a,
+            new_parent: parent_id(&b_parent),
+        });
+        self.emit(TreeEvent::Moved {
+            id: b,
+            new_parent: parent_id(&a_parent),
+        });
+        true
+    }
+
+    /// Outdent: make a node the next sibling of its parent (a root, if the
+    /// parent is a root). Returns `false` if it is a root already, not found,
+    /// or the grandparent is at the `max_children` cap.
+    pub fn promote(&mut self, id: u32) -> bool {
+        let Some(parent) = self
+            .index
+            .get(&id)
+            .and_then(|node_ref| node_ref.borrow().parent.clone())
+            .and_then(|parent| parent.upgrade())
+        else {
+            return false;
+        };
+        let parent_id = parent.borrow().id;
+        let Some(parent_pos) = self.sibling_position(parent_id) else {
+            return false;
+        };
+
+        let grandparent = parent.borrow().parent.as_ref().and_then(Weak::upgrade);
+        match grandparent {
+            Some(grandparent) => {
+                let grandparent_id = grandparent.borrow().id;
+                self.move_node_at(id, grandparent_id, parent_pos + 1)
+                    .is_ok()
+            }
+            None => {
+                // `move_node_at` only targets parents; roots are uncapped
+                let node_ref = Rc::clone(&self.index[&id]);
+                let Some(from) = self.detach(&node_ref) else {
+                    return false;
+                };
+                let position = self.attach_at(&node_ref, None, parent_pos + 1);
+                node_ref.borrow_mut().touch();
+
+                self.record(Edit::Moved {
+                    id,
+                    from,
+                    to: (None, position),
+                });
+                true
+            }
+        }
+    }
+
+    /// Indent: make a node the last child of its previous sibling. Returns
+    /// `false` if it is the first sibling, not found, or that sibling is at
+    /// the `max_children` cap.
+    pub fn demote(&mut self, id: u32) -> bool {
+        let Some(prev_id) = self.sibling_at(id, -1) else {
+            return false;
+        };
+        self.move_node(id, prev_id).is_ok()
+    }
+
+    /// Revert the most recent recorded edit. Returns `false` if there is
+    /// nothing to undo, or if the edit no longer applies (e.g. a node it
+    /// refers to was dropped by an unrecorded operation such as
+    /// `rebuild_index`). An edit that no longer applies is discarded, so the
+    /// next call tries the one before it; check `can_undo` first to tell the
+    /// two cases apart.
+    ///
+    /// Recorded edits are `add_root`, `add_child`, `toggle`, `delete`,
+    /// `move_node`, `swap` and reorders (the sorts, `reverse_children`,
+    /// `move_up`/`move_down`). A deleted subtree comes back at its original
+    /// position, with its `rename_undo` titles.
+    pub fn undo(&mut self) -> bool {
+        let Some(edit) = self.undo_stack.pop_back() else {
+            return false;
+        };
+
+        if !self.revert(&edit) {
+            return false;
+        }
+        if let Some(event) = Self::edit_event(&edit, true) {
+            self.emit(event);
+        }
+
+        self.redo_stack.push(edit);
+        true
+    }
+
+    /// Re-apply the most recently undone edit. Returns `false` if there is
+    /// nothing to redo or it no longer applies (it is then discarded, as in
+    /// `undo`). Any new recorded edit clears the redo stack.
+    pub fn redo(&mut self) -> bool {
+        let Some(edit) = self.redo_stack.pop() else {
+            return false;
+        };
+
+        if !self.reapply(&edit) {
+            return false;
+        }
+        if let Some(event) = Self::edit_event(&edit, false) {
+            self.emit(event);
+        }
+
+        self.undo_stack.push_back(edit);
+        self.trim_history();
+        true
+    }
+
+    /// Whether there is an edit for `undo` to try
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    /// Whether there is an edit for `redo` to try
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Limit how many edits the undo and redo stacks keep (oldest dropped first)
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        self.trim_history();
+        self.redo_stack.truncate(limit);
+    }
+
+    /// Register a callback for every change to the tree (see `TreeEvent`).
+    /// Listeners run synchronously, in registration order, and are not
+    /// carried over by `clone`.
+    pub fn on_change(&mut self, f: Listener) {
+        self.listeners.get_mut().push(f);
+    }
+
+    /// Forget all undo/redo history
+    pub fn clear_history(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+
+    /// Move a node (and its subtree) under the archive root, creating the
+    /// archive root if needed. Returns `true` on success.
+    ///
+    /// Fails if the id is missing or is the archive root itself.
+    pub fn archive(&mut self, id: u32) -> bool
+    where
+        T: Default,
+    {
+        if !self.index.contains_key(&id) || self.archive_root_id() == Some(id) {
+            return false;
+        }
+
+        let archive_id = match self.archive_root_id() {
+            Some(archive_id) => archive_id,
+            None => self.add_root(ARCHIVE_TITLE),
+        };
+
+        self.move_node(id, archive_id).is_ok()
+    }
+
+    /// Archive every maximal non-root subtree in which all nodes are done.
+    /// Returns the ids of the archived subtree roots and of those left in
+    /// place because the archive root is at the `max_children` cap, both in
+    /// tree order.
+    ///
+    /// Whole root trees are never archived here, nor any branch of them,
+    /// even when fully done; use `completed_roots` + `archive` for those so
+    /// the caller can confirm first.
+    pub fn archive_completed_branches(&mut self) -> (Vec<u32>, Vec<u32>)
+    where
+        T: Default,
+    {
+        let archive_id = self.archive_root_id();
+
+        let mut found = Vec::new();
+        let mut stack: Vec<NodeRef<T>> = Vec::new();
+        for root in self.roots.iter().rev() {
+            // A fully done root is left whole for `completed_roots`
+            if Some(root.borrow().id) == archive_id || Self::subtree_all_done(root) {
+                continue;
+            }
+            stack.extend(root.borrow().children.iter().rev().cloned());
+        }
+
+        while let Some(node_ref) = stack.pop() {
+            if Self::subtree_all_done(&node_ref) {
+                // Maximal: don't look further down this branch
+                found.push(node_ref.borrow().id);
+            } else {
+                stack.extend(node_ref.borrow().children.iter().rev().cloned());
+            }
+        }
+
+        found.into_iter().partition(|&id| self.archive(id))
+    }
+
+    /// Ids of root trees (other than the archive) in which every node is done
+    pub fn completed_roots(&self) -> Vec<u32> {
+        let archive_id = self.archive_root_id();
+
+        self.roots
+            .iter()
+            .filter(|root_ref| Some(root_ref.borrow().id) != archive_id)
+            .filter(|root_ref| Self::subtree_all_done(root_ref))
+            .map(|root_ref| root_ref.borrow().id)
+            .collect()
+    }
+
+    /// Delete every maximal subtree in which all nodes are done, roots
+    /// included, so no done node is left behind without its done parent.
+    /// Branches with any undone node keep that node and its ancestors.
+    /// Returns the number of nodes removed; each subtree is one undo step.
+    pub fn prune_done(&mut self) -> usize {
+        let mut found = Vec::new();
+        let mut stack: Vec<NodeRef<T>> = self.roots.iter().rev().cloned().collect();
+        while let Some(node_ref) = stack.pop() {
+            if Self::subtree_all_done(&node_ref) {
+                // Maximal: the whole branch goes at once
+                found.push(node_ref.borrow().id);
+            } else {
+                stack.extend(node_ref.borrow().children.iter().rev().cloned());
+            }
+        }
+
+        let mut removed = 0;
+        for id in found {
+            let size = self.subtree_size(id).unwrap_or(0);
+            if self.delete(id).is_ok() {
+                removed += size;
+            }
+        }
+
+        removed
+    }
+
+    /// Get a read-only handle to a node.
+    pub fn get(&self, id: u32) -> Option<NodeRef<T>> {
+        self.index.get(&id).cloned()
+    }
+
+    /// Number of nodes in the tree
+    pub fn node_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Number of root nodes
+    pub fn root_count(&self) -> usize {
+        self.roots.len()
+    }
+
+    /// Number of parent -> child links. Every non-root node has exactly one.
+    pub fn edge_count(&self) -> usize {
+        self.index.len() - self.roots.len()
+    }
+
+    /// Number of nodes that have at least one child
+    pub fn branch_count(&self) -> usize {
+        self.index
+            .values()
+            .filter(|node_ref| !node_ref.borrow().children.is_empty())
+            .count()
+    }
+
+    /// One-line description for logs, e.g.
+    /// `"3 roots, 14 nodes, 6 done, max depth 4"` (roots are depth 0),
+    /// or `"empty tree"`.
+    pub fn summary(&self) -> String {
+        if self.index.is_empty() {
+            return "empty tree".into();
+        }
+
+        let stats = self.stats();
+        let plural =
+            |n: usize, word: &str| format!("{} {}{}", n, word, if n == 1 { "" } else { "s" });
+        format!(
+            "{}, {}, {} done, max depth {}",
+            plural(stats.root_count, "root"),
+            plural(stats.total_nodes, "node"),
+            stats.done_nodes,
+            stats.max_depth
+        )
+    }
+
+    /// Number of nodes in the subtree at `id` (including itself), counted by
+    /// walking `children`. Returns `None` if not found.
+    pub fn subtree_size(&self, id: u32) -> Option<usize> {
+        let mut count = 0;
+
+        let mut stack = vec![Rc::clone(self.index.get(&id)?)];
+        while let Some(node_ref) = stack.pop() {
+            count += 1;
+            stack.extend(node_ref.borrow().children.iter().cloned());
+        }
+
+        Some(count)
+    }
+
+    /// Node, completion, leaf and depth counts, gathered in one traversal.
+    /// An empty tree reports all zeros.
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {
+            root_count: self.roots.len(),
+            ..TreeStats::default()
+        };
+
+        let mut stack: Vec<(NodeRef<T>, usize)> = self
+            .roots
+            .iter()
+            .map(|root_ref| (Rc::clone(root_ref), 0))
+            .collect();
+        while let Some((node_ref, depth)) = stack.pop() {
+            let node = node_ref.borrow();
+            stats.total_nodes += 1;
+            if node.done {
+                stats.done_nodes += 1;
+            }
+            if node.children.is_empty() {
+                stats.leaf_count += 1;
+            }
+            stats.max_depth = stats.max_depth.max(depth);
+
+            for child in &node.children {
+                stack.push((Rc::clone(child), depth + 1));
+            }
+        }
+
+        stats
+    }
+
+    /// Return the child ids of `id`, ordered by `cmp`. Returns `None` if not found.
+    ///
+    /// The stored `children` order is left untouched, so display code can
+    /// present sorted views (by title, by done flag, ...) without mutating the tree.
+    pub fn children_view(
+        &self,
+        id: u32,
+        cmp: impl Fn(&Node<T>, &Node<T>) -> Ordering,
+    ) -> Option<Vec<u32>> {
+        let node_ref = self.index.get(&id)?;
+        let node = node_ref.borrow();
+
+        let mut children = node.children.to_vec();
+        children.sort_by(|a, b| cmp(&a.borrow(), &b.borrow()));
+
+        Some(children.iter().map(|child| child.borrow().id).collect())
+    }
+
+    /// Return whether `target_id` lies in the subtree rooted at `root_id`
+    /// (a node contains itself). Returns `None` if `root_id` is not found.
+    ///
+    /// Walks upward from the target, so the cost is bounded by its depth.
+    pub fn subtree_contains(&self, root_id: u32, target_id: u32) -> Option<bool> {
+        if !self.index.contains_key(&root_id) {
+            return None;
+        }
+
+        let mut current = self.index.get(&target_id).cloned();
+        while let Some(node_ref) = current {
+            let node = node_ref.borrow();
+            if node.id == root_id {
+                return Some(true);
+            }
+            current = node.parent.as_ref().and_then(|parent| parent.upgrade());
+        }
+
+        Some(false)
+    }
+
+    /// Rebuild `index` from the `roots` graph. Returns the number of nodes indexed.
+    ///
+    /// Recovery primitive for when the index has drifted from the actual
+    /// structure. `next_id` is bumped past the largest id found if needed.
+    pub fn rebuild_index(&mut self) -> usize {
+        self.index.clear();
+
+        let mut max_id = None;
+        let mut stack: Vec<NodeRef<T>> = self.roots.iter().rev().cloned().collect();
+        while let Some(node_ref) = stack.pop() {
+            let node = node_ref.borrow();
+            max_id = max_id.max(Some(node.id));
+            stack.extend(node.children.iter().rev().cloned());
+            self.index.insert(node.id, Rc::clone(&node_ref));
+        }
+
+        if let Some(max_id) = max_id {
+            self.next_id = self.next_id.max(max_id + 1);
+        }
+
+        self.index.len()
+    }
+
+    /// Point the index entry for `id` at `node`, or drop it with `None`, so
+    /// tests can corrupt the index without touching the structure
+    #[cfg(test)]
+    fn set_index_entry(&mut self, id: u32, node: Option<NodeRef<T>>) {
+        match node {
+            Some(node) => self.index.insert(id, node),
+            None => self.index.remove(&id),
+        };
+    }
+
+    /// Id of the first node in pre-order satisfying `pred`.
+    ///
+    /// Stops as soon as a match is found, so nodes after it are never visited.
+    pub fn find_first(&self, pred: impl Fn(&Node<T>) -> bool) -> Option<u32> {
+        let mut stack: Vec<NodeRef<T>> = self.roots.iter().rev().cloned().collect();
+        while let Some(node_ref) = stack.pop() {
+            let node = node_ref.borrow();
+            if pred(&node) {
+                return Some(node.id);
+            }
+            stack.extend(node.children.iter().rev().cloned());
+        }
+
+        None
+    }
+
+    /// Ids of every node without children, in pre-order
+    pub fn leaves(&self) -> Vec<u32> {
+        self.iter_dfs()
+            .filter(|node_ref| node_ref.borrow().children.is_empty())
+            .map(|node_ref| node_ref.borrow().id)
+            .collect()
+    }
+
+    /// Number of nodes without children
+    pub fn leaf_count(&self) -> usize {
+        self.index
+            .values()
+            .filter(|node_ref| node_ref.borrow().children.is_empty())
+            .count()
+    }
+
+    /// Pending leaf ids ordered as a work queue: highest priority first,
+    /// ties kept in pre-order. Done nodes and parents are excluded.
+    pub fn pending_leaves_by_priority(&self) -> Vec<u32> {
+        let mut leaves: Vec<(u8, u32)> = self
+            .preorder()
+            .iter()
+            .map(|node_ref| node_ref.borrow())
+            .filter(|node| node.children.is_empty() && !node.done)
+            .map(|node| (node.priority, node.id))
+            .collect();
+
+        // Stable, so equal priorities keep their pre-order
+        leaves.sort_by(|(a, _), (b, _)| b.cmp(a));
+        leaves.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// Every node in pre-order: each root in order, then its children in
+    /// stored order, depth first.
+    pub fn iter_dfs(&self) -> DfsIter<T> {
+        DfsIter {
+            stack: self.roots.iter().rev().cloned().collect(),
+        }
+    }
+
+    /// Every node level by level: all roots in order, then all their
+    /// children left to right, and so on.
+    pub fn iter_bfs(&self) -> BfsIter<T> {
+        BfsIter {
+            queue: self.roots.iter().cloned().collect(),
+        }
+    }
+
+    /// Call `f` on every node in pre-order (see `iter_dfs`)
+    pub fn for_each<F: FnMut(&Node<T>)>(&self, mut f: F) {
+        for node_ref in self.iter_dfs() {
+            f(&node_ref.borrow());
+        }
+    }
+
+    /// Like `for_each`, with mutable access. Done flags changed this way are
+    /// not propagated; call `recompute_all_done` afterwards if needed.
+    pub fn for_each_mut<F: FnMut(&mut Node<T>)>(&mut self, mut f: F) {
+        for node_ref in self.iter_dfs() {
+            f(&mut node_ref.borrow_mut());
+        }
+    }
+
+    /// Number of nodes satisfying `pred`
+    pub fn count_where<F: Fn(&Node<T>) -> bool>(&self, pred: F) -> usize {
+        self.iter_dfs()
+            .filter(|node_ref| pred(&node_ref.borrow()))
+            .count()
+    }
+
+    /// Visit every node so that all descendants come before their ancestor
+    /// (post-order, children in stored order, roots in order).
+    ///
+    /// Iterative two-stack walk, so deep trees don't exhaust the call stack.
+    pub fn for_each_post_order(&self, mut f: impl FnMut(&Node<T>)) {
+        let mut pending: Vec<NodeRef<T>> = self.roots.to_vec();
+        let mut output: Vec<NodeRef<T>> = Vec::with_capacity(self.index.len());
+        while let Some(node_ref) = pending.pop() {
+            pending.extend(node_ref.borrow().children.iter().cloned());
+            output.push(node_ref);
+        }
+
+        for node_ref in output.iter().rev() {
+            f(&node_ref.borrow());
+        }
+    }
+
+    /// Ids of nodes whose title contains `term` (case-insensitive), in tree order
+    pub fn search(&self, term: &str) -> Vec<u32> {
+        self.find_any(&[term])
+    }
+
+    /// Ids of nodes whose title matches the regular expression `pattern`
+    /// anywhere, in pre-order. Uses the `regex` crate's syntax, so matching
+    /// is case-sensitive unless the pattern starts with `(?i)`.
+    pub fn search_regex(&self, pattern: &str) -> Result<Vec<u32>, regex::Error> {
+        let regex = Regex::new(pattern)?;
+        Ok(self
+            .preorder()
+            .iter()
+            .map(|node_ref| node_ref.borrow())
+            .filter(|node| regex.is_match(&node.title))
+            .map(|node| node.id)
+            .collect())
+    }
+
+    /// Ids from the root down to `id` (inclusive), or `None` if not found
+    pub fn path_to(&self, id: u32) -> Option<Vec<u32>> {
+        let lineage = self.lineage(id)?;
+        Some(
+            lineage
+                .iter()
+                .map(|node_ref| node_ref.borrow().id)
+                .collect(),
+        )
+    }
+
+    /// Lowest common ancestor: the deepest node whose subtree holds both `a`
+    /// and `b` (one of them, if it contains the other). `None` if either id
+    /// is missing or they sit in different root trees.
+    pub fn lca(&self, a: u32, b: u32) -> Option<u32> {
+        let path_a = self.path_to(a)?;
+        let path_b = self.path_to(b)?;
+
+        path_a
+            .iter()
+            .zip(&path_b)
+            .take_while(|(x, y)| x == y)
+            .last()
+            .map(|(&id, _)| id)
+    }
+
+    /// Number of edges on the path from `a` to `b` through their lowest
+    /// common ancestor (0 when `a == b`). `None` if either id is missing or
+    /// they sit in different root trees.
+    pub fn distance(&self, a: u32, b: u32) -> Option<usize> {
+        let path_a = self.path_to(a)?;
+        let path_b = self.path_to(b)?;
+
+        let shared = path_a
+            .iter()
+            .zip(&path_b)
+            .take_while(|(x, y)| x == y)
+            .count();
+        if shared == 0 {
+            return None;
+        }
+
+        Some(path_a.len() + path_b.len() - 2 * shared)
+    }
+
+    /// Id of the node reached by following exact titles from the roots
+    /// (`["Work", "Backlog"]` is the "Backlog" child of the "Work" root).
+    /// `None` if a segment is missing or matches more than one sibling.
+    pub fn resolve_path(&self, segments: &[&str]) -> Option<u32> {
+        self.lookup_path(segments).ok()
+    }
+
+    /// Like `resolve_path`, but says whether the path was missing or
+    /// ambiguous, naming the prefix where the walk stopped.
+    pub fn lookup_path(&self, segments: &[&str]) -> Result<u32, TreeError> {
+        let mut walked = String::new();
+        let mut current: Option<NodeRef<T>> = None;
+        for segment in segments {
+            walked.push('/');
+            walked.push_str(segment);
+
+            let mut matches = {
+                let candidates = match &current {
+                    Some(node_ref) => node_ref.borrow().children.clone(),
+                    None => self.roots.clone(),
+                };
+                candidates
+                    .into_iter()
+                    .filter(|node_ref| node_ref.borrow().title == *segment)
+                    .collect::<Vec<_>>()
+            };
+            if matches.len() > 1 {
+                return Err(TreeError::AmbiguousPath(walked));
+            }
+            current = Some(
+                matches
+                    .pop()
+                    .ok_or(TreeError::PathNotFound(walked.clone()))?,
+            );
+        }
+
+        current
+            .map(|node_ref| node_ref.borrow().id)
+            .ok_or(TreeError::PathNotFound("/".to_string()))
+    }
+
+    /// Ids of nodes whose title contains at least one of `terms`
+    /// (case-insensitive), in pre-order.
+    pub fn find_any(&self, terms: &[&str]) -> Vec<u32> {
+        let terms: Vec<String> = terms.iter().map(|term| term.to_lowercase()).collect();
+        self.find_by_title(|title| terms.iter().any(|term| title.contains(term.as_str())))
+    }
+
+    /// Ids of nodes whose title contains every one of `terms`
+    /// (case-insensitive), in pre-order.
+    pub fn find_all(&self, terms: &[&str]) -> Vec<u32> {
+        let terms: Vec<String> = terms.iter().map(|term| term.to_lowercase()).collect();
+        self.find_by_title(|title| terms.iter().all(|term| title.contains(term.as_str())))
+    }
+
+    /// Compare two forests by shape, titles and done flags. Child (and root)
+    /// order is significant; ids, payloads and all other fields are not, so
+    /// a tree still equals itself after a save/load or `merge` renumbering.
+    pub fn structurally_eq(&self, other: &Tree<T>) -> bool {
+        if self.roots.len() != other.roots.len() {
+            return false;
+        }
+
+        let mut stack: Vec<(NodeRef<T>, NodeRef<T>)> = self
+            .roots
+            .iter()
+            .cloned()
+            .zip(other.roots.iter().cloned())
+            .collect();
+        while let Some((a_ref, b_ref)) = stack.pop() {
+            let (a, b) = (a_ref.borrow(), b_ref.borrow());
+            if a.title != b.title || a.done != b.done || a.children.len() != b.children.len() {
+                return false;
+            }
+
+            stack.extend(a.children.iter().cloned().zip(b.children.iter().cloned()));
+        }
+
+        true
+    }
+
+    /// The roots as owned `SerializableNode` trees, in order
+    pub fn to_serializable(&self) -> Vec<SerializableNode> {
+        Self::fold_subtrees(&self.roots, Self::node_to_serializable)
+    }
+
+    /// Rebuild a tree from `to_serializable` output: parent links, the index
+    /// and `next_id` are restored, and done flags are kept as given.
+    /// Fails if an id appears twice.
+    pub fn from_serializable(roots: Vec<SerializableNode>) -> Result<Self, TreeError>
+    where
+        T: Default,
+    {
+        let mut tree = Tree::default();
+
+        let mut stack: Vec<(SerializableNode, Option<NodeRef<T>>)> =
+            roots.into_iter().rev().map(|root| (root, None)).collect();
+        while let Some((mut item, parent)) = stack.pop() {
+            if tree.index.contains_key(&item.id) {
+                return Err(TreeError::DuplicateId(item.id));
+            }
+
+            let node_ref = Node::new(item.id, std::mem::take(&mut item.title), None);
+            node_ref.borrow_mut().done = item.done;
+            tree.attach_raw(&node_ref, parent.as_ref());
+
+            for child in std::mem::take(&mut item.children).into_iter().rev() {
+                stack.push((child, Some(Rc::clone(&node_ref))));
+            }
+        }
+
+        Ok(tree)
+    }
+
+    /// Materialize `TreeBuilder` roots into a tree. Ids are handed out in
+    /// pre-order from 1; done flags are kept as given (not derived from
+    /// children), and the undo history starts empty.
+    pub fn from_builder(roots: impl IntoIterator<Item = TreeBuilder>) -> Self
+    where
+        T: Default,
+    {
+        let mut tree = Tree::default();
+
+        let roots: Vec<TreeBuilder> = roots.into_iter().collect();
+        let mut stack: Vec<(TreeBuilder, Option<NodeRef<T>>)> =
+            roots.into_iter().rev().map(|root| (root, None)).collect();
+        while let Some((item, parent)) = stack.pop() {
+            let id = tree.alloc_id();
+            let node_ref = Node::new(id, item.title, None);
+            node_ref.borrow_mut().set_done(item.done);
+            tree.attach_raw(&node_ref, parent.as_ref());
+
+            for child in item.children.into_iter().rev() {
+                stack.push((child, Some(Rc::clone(&node_ref))));
+            }
+        }
+
+        tree
+    }
+
+    /// Flatten the tree into one record per node.
+    ///
+    /// The order is stable pre-order: roots in order, each node followed by its
+    /// subtree in stored child order, so a parent always precedes its children.
+    /// Exporters should build on this rather than re-walking the tree.
+    pub fn to_flat_records(&self) -> Vec<FlatRecord> {
+        let mut records = Vec::with_capacity(self.index.len());
+
+        let mut stack: Vec<(NodeRef<T>, Option<u32>, usize)> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|root_ref| (Rc::clone(root_ref), None, 0))
+            .collect();
+        while let Some((node_ref, parent_id, depth)) = stack.pop() {
+            let node = node_ref.borrow();
+            for child in node.children.iter().rev() {
+                stack.push((Rc::clone(child), Some(node.id), depth + 1));
+            }
+
+            records.push(FlatRecord {
+                id: node.id,
+                parent_id,
+                depth,
+                done: node.done,
+                title: node.title.clone(),
+            });
+        }
+
+        records
+    }
+
+    /// `(id, title, done)` for every node, in the same pre-order as
+    /// `to_flat_records`
+    pub fn list(&self) -> Vec<(u32, String, bool)> {
+        self.to_flat_records()
+            .into_iter()
+            .map(|record| (record.id, record.title, record.done))
+            .collect()
+    }
+
+    /// `(depth, id)` for every node, in the same pre-order as
+    /// `to_flat_records` (roots are depth 0)
+    pub fn flatten(&self) -> Vec<(usize, u32)> {
+        self.to_flat_records()
+            .into_iter()
+            .map(|record| (record.depth, record.id))
+            .collect()
+    }
+
+    /// Save the whole forest (ids, titles, done flags, structure, ...) to a
+    /// compact JSON file.
+    ///
+    /// The file is replaced atomically: the JSON goes to a temporary file in
+    /// the same directory, which is then renamed over `path`, so an
+    /// interrupted save leaves the old file intact. The previous version is
+    /// kept next to it as `<path>.bak`.
+    pub fn save_json(&self, path: &Path) -> io::Result<()> {
+        write_atomic(path, &self.to_json_string(false))
+    }
+
+    /// Like `save_json`, but 2-space indented for hand-editing
+    pub fn save_json_pretty(&self, path: &Path) -> io::Result<()> {
+        write_atomic(path, &self.to_json_string(true))
+    }
+
+    /// Load a tree saved by `save_json`/`save_json_pretty`, rebuilding the
+    /// roots, index and parent links. `next_id` is one past the largest id.
+    ///
+    /// A malformed file is reported as `io::ErrorKind::InvalidData`.
+    pub fn load_json(path: &Path) -> io::Result<Self>
+    where
+        T: Default,
+    {
+        let contents = fs::read_to_string(path)?;
+        Tree::from_json_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Serialize the whole forest to JSON. `pretty` gives 2-space indented
+    /// output for hand-editing; the compact form is meant for programs.
+    ///
+    /// Format: `{"next_id": N, "roots": [...]}` where each node is an object
+    /// with its fields (`id`, `title`, `done`, ...) and a nested `children` array.
+    pub fn to_json_string(&self, pretty: bool) -> String {
+        let roots = Self::fold_subtrees(&self.roots, Self::node_to_json);
+        let doc = Value::Object(vec![
+            ("next_id".into(), Value::Number(self.next_id as f64)),
+            ("roots".into(), Value::Array(roots)),
+        ]);
+
+        doc.to_json(pretty)
+    }
+
+    /// Rebuild a tree from the output of `to_json_string` (either form).
+    ///
+    /// Ids, done flags and child order are kept as stored; `next_id` ends up
+    /// past every id in the document.
+    pub fn from_json_str(input: &str) -> Result<Self, String>
+    where
+        T: Default,
+    {
+        let doc = json::parse(input)?;
+        let roots = doc
+            .get("roots")
+            .and_then(Value::as_array)
+            .ok_or("missing \"roots\" array")?;
+
+        let mut tree = Tree::default();
+        let mut stack: Vec<(&Value, Option<NodeRef<T>>)> =
+            roots.iter().rev().map(|value| (value, None)).collect();
+        while let Some((value, parent)) = stack.pop() {
+            let id = value
+                .get("id")
+                .and_then(Value::as_u64)
+                .and_then(|id| u32::try_from(id).ok())
+                .ok_or("node is missing a valid \"id\"")?;
+            let title = value
+                .get("title")
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("node {} is missing \"title\"", id))?;
+            let done = value
+                .get("done")
+                .and_then(Value::as_bool)
+                .ok_or_else(|| format!("node {} is missing \"done\"", id))?;
+            let priority = match value.get("priority") {
+                None => 0,
+                Some(priority) => priority
+                    .as_u64()
+                    .and_then(|priority| u8::try_from(priority).ok())
+                    .ok_or_else(|| format!("node {}: \"priority\" must be 0-255", id))?,
+            };
+            let weight = match value.get("weight") {
+                None => 1,
+                Some(weight) => weight
+                    .as_u64()
+                    .and_then(|weight| u32::try_from(weight).ok())
+                    .ok_or_else(|| {
+                        format!("node {}: \"weight\" must be a non-negative integer", id)
+                    })?,
+            };
+            let tags = match value.get("tags") {
+                None => Vec::new(),
+                Some(tags) => tags
+                    .as_array()
+                    .and_then(|tags| tags.iter().map(Value::as_str).collect::<Option<Vec<_>>>())
+                    .ok_or_else(|| format!("node {}: \"tags\" must be an array of strings", id))?,
+            };
+            let completed_at = time_from_json(value, id, "completed_at")?;
+            let due = match value.get("due") {
+                None | Some(Value::Null) => None,
+                Some(due) => Some(
+                    due.as_str()
+                        .ok_or_else(|| format!("node {}: \"due\" must be a date string", id))?
+                        .parse::<Date>()
+                        .map_err(|e| format!("node {}: {}", id, e))?,
+                ),
+            };
+            let created_at = time_from_json(value, id, "created_at")?;
+            let modified_at = time_from_json(value, id, "modified_at")?;
+            let children = match value.get("children") {
+                None => &[][..],
+                Some(children) => children
+                    .as_array()
+                    .ok_or_else(|| format!("node {}: \"children\" must be an array", id))?,
+            };
+
+            if tree.index.contains_key(&id) {
+                return Err(format!("duplicate id {}", id));
+            }
+
+            let node = Node::new(id, title, None);
+            {
+                let mut node = node.borrow_mut();
+                node.done = done;
+                node.priority = priority;
+                node.weight = weight;
+                for tag in tags {
+                    // Keep tags de-duplicated even if the file repeats one
+                    if !node.tags.iter().any(|existing| existing == tag) {
+                        node.tags.push(tag.to_string());
+                    }
+                }
+                node.completed_at = completed_at;
+                node.due = due;
+                // Older files have no timestamps; keep the load time for those
+                if let Some(created_at) = created_at {
+                    node.created_at = created_at;
+                }
+                if let Some(modified_at) = modified_at {
+                    node.modified_at = modified_at;
+                }
+            }
+            tree.attach_raw(&node, parent.as_ref());
+
+            for child in children.iter().rev() {
+                stack.push((child, Some(Rc::clone(&node))));
+            }
+        }
+
+        if let Some(next_id) = doc.get("next_id").and_then(Value::as_u64) {
+            tree.next_id = tree.next_id.max(next_id.min(u32::MAX as u64) as u32);
+        }
+
+        Ok(tree)
+    }
+
+    /// Tag a node. Returns `false` if not found or it already has the tag.
+    pub fn add_tag(&mut self, id: u32, tag: impl Into<String>) -> bool {
+        let Some(node_ref) = self.index.get(&id) else {
+            return false;
+        };
+
+        let tag = tag.into();
+        let mut node = node_ref.borrow_mut();
+        if node.tags.contains(&tag) {
+            return false;
+        }
+        node.tags.push(tag);
+        node.touch();
+        true
+    }
+
+    /// Remove a tag from a node. Returns `false` if not found or it didn't
+    /// have the tag.
+    pub fn remove_tag(&mut self, id: u32, tag: &str) -> bool {
+        let Some(node_ref) = self.index.get(&id) else {
+            return false;
+        };
+
+        let mut node = node_ref.borrow_mut();
+        let Some(pos) = node.tags.iter().position(|existing| existing == tag) else {
+            return false;
+        };
+        node.tags.remove(pos);
+        node.touch();
+        true
+    }
+
+    /// Ids of unfinished nodes due before `today`, in pre-order
+    pub fn overdue(&self, today: Date) -> Vec<u32> {
+        self.preorder()
+            .iter()
+            .map(|node_ref| node_ref.borrow())
+            .filter(|node| !node.done && node.due.is_some_and(|due| due < today))
+            .map(|node| node.id)
+            .collect()
+    }
+
+    /// Ids of nodes carrying `tag` (exact match), in pre-order
+    pub fn find_by_tag(&self, tag: &str) -> Vec<u32> {
+        self.preorder()
+            .iter()
+            .map(|node_ref| node_ref.borrow())
+            .filter(|node| node.tags.iter().any(|existing| existing == tag))
+            .map(|node| node.id)
+            .collect()
+    }
+
+    /// Each tag in use and how many nodes carry it, sorted by count
+    /// (descending) then tag name.
+    pub fn tag_histogram(&self) -> Vec<(String, usize)> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for node_ref in self.index.values() {
+            for tag in &node_ref.borrow().tags {
+                *counts.entry(tag.clone()).or_insert(0) += 1;
+            }
+        }
+
+        let mut histogram: Vec<(String, usize)> = counts.into_iter().collect();
+        histogram.sort_by(|(tag_a, count_a), (tag_b, count_b)| {
+            count_b.cmp(count_a).then_with(|| tag_a.cmp(tag_b))
+        });
+
+        histogram
+    }
+
+    /// Export as tab-separated values with a header row: `id`, `depth`,
+    /// `done`, `title`, one row per node in pre-order.
+    ///
+    /// TSV has no real quoting, so tabs and line breaks inside titles are
+    /// replaced with spaces.
+    pub fn to_tsv(&self) -> String {
+        let mut out = String::from("id\tdepth\tdone\ttitle\n");
+        for record in self.to_flat_records() {
+            let title: String = record
+                .title
+                .chars()
+                .map(|c| {
+                    if matches!(c, '\t' | '\n' | '\r') {
+                        ' '
+                    } else {
+                        c
+                    }
+                })
+                .collect();
+            out.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                record.id, record.depth, record.done, title
+            ));
+        }
+
+        out
+    }
+
+    /// The subtree under `id` as a plain indented outline (`[x] title` /
+    /// `[ ] title`, two spaces per level, no ids), re-based so `id` itself
+    /// is at indent 0. Returns `None` if not found.
+    pub fn subtree_to_outline(&self, id: u32) -> Option<String> {
+        let mut out = String::new();
+
+        let mut stack = vec![(self.index.get(&id)?.clone(), 0)];
+        while let Some((node_ref, indent)) = stack.pop() {
+            let node = node_ref.borrow();
+            out.push_str(&"  ".repeat(indent));
+            out.push_str(&format!(
+                "[{}] {}\n",
+                if node.done { "x" } else { " " },
+                node.title
+            ));
+
+            for child in node.children.iter().rev() {
+                stack.push((Rc::clone(child), indent + 1));
+            }
+        }
+
+        Some(out)
+    }
+
+    /// Export as JSON Lines: one `{"id", "parent", "done", "title"}` object
+    /// per node in pre-order, `parent` being `null` for roots.
+    pub fn to_jsonl(&self) -> String {
+        let mut out = String::new();
+        for record in self.to_flat_records() {
+            let line = Value::Object(vec![
+                ("id".into(), Value::Number(record.id as f64)),
+                (
+                    "parent".into(),
+                    match record.parent_id {
+                        Some(parent_id) => Value::Number(parent_id as f64),
+                        None => Value::Null,
+                    },
+                ),
+                ("done".into(), Value::Bool(record.done)),
+                ("title".into(), Value::String(record.title)),
+            ]);
+            out.push_str(&line.to_json(false));
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// One node as a compact JSON object: `id`, `title`, `done`, `children`
+    /// (child ids, in order) and `parent` (id or null). `None` if missing.
+    pub fn node_json(&self, id: u32) -> Option<String> {
+        let node_ref = self.index.get(&id)?;
+        let node = node_ref.borrow();
+
+        let parent = node.parent.as_ref().and_then(Weak::upgrade);
+        let json = Value::Object(vec![
+            ("id".into(), Value::Number(node.id as f64)),
+            ("title".into(), Value::String(node.title.clone())),
+            ("done".into(), Value::Bool(node.done)),
+            (
+                "children".into(),
+                Value::Array(
+                    node.children
+                        .iter()
+                        .map(|child_ref| Value::Number(child_ref.borrow().id as f64))
+                        .collect(),
+                ),
+            ),
+            (
+                "parent".into(),
+                match parent {
+                    Some(parent_ref) => Value::Number(parent_ref.borrow().id as f64),
+                    None => Value::Null,
+                },
+            ),
+        ]);
+
+        Some(json.to_json(false))
+    }
+
+    /// Load a tree from JSON Lines as written by `to_jsonl`.
+    ///
+    /// Lines may come in any order: all records are collected first, then
+    /// linked, with siblings kept in the order they appear. Ids are kept and
+    /// `next_id` is set past the largest. Blank lines are ignored; malformed
+    /// lines and records with a blank title are reported with their line
+    /// number.
+    pub fn from_jsonl(reader: impl BufRead) -> Result<Self, String>
+    where
+        T: Default,
+    {
+        // Pass 1: collect every record
+        let mut records: Vec<(usize, u32, Option<u32>, bool, String)> = Vec::new();
+        let mut seen = HashSet::new();
+        for (line_no, line) in reader.lines().enumerate() {
+            let line_no = line_no + 1;
+            let line = line.map_err(|e| format!("line {}: {}", line_no, e))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let value = json::parse(&line).map_err(|e| format!("line {}: {}", line_no, e))?;
+            let id = value
+                .get("id")
+                .and_then(Value::as_u64)
+                .and_then(|id| u32::try_from(id).ok())
+                .ok_or_else(|| format!("line {}: missing or invalid \"id\"", line_no))?;
+            let parent = match value.get("parent") {
+                None | Some(Value::Null) => None,
+                Some(parent) => Some(
+                    parent
+                        .as_u64()
+                        .and_then(|parent| u32::try_from(parent).ok())
+                        .ok_or_else(|| format!("line {}: invalid \"parent\"", line_no))?,
+                ),
+            };
+            let done = value
+                .get("done")
+                .and_then(Value::as_bool)
+                .ok_or_else(|| format!("line {}: missing or invalid \"done\"", line_no))?;
+            let title = value
+                .get("title")
+                .and_then(Value::as_str)
+                .ok_or_else(|| format!("line {}: missing or invalid \"title\"", line_no))?;
+
+            if !seen.insert(id) {
+                return Err(format!("line {}: duplicate id {}", line_no, id));
+            }
+            records.push((line_no, id, parent, done, title.to_string()));
+        }
+
+        Self::from_parent_records(records)
+    }
+
+    /// Export as CSV with a header row `id,parent_id,title,done`, one row
+    /// per node in pre-order. `parent_id` is empty for roots; titles are
+    /// quoted when they contain commas, quotes or line breaks.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("id,parent_id,title,done\n");
+        for record in self.to_flat_records() {
+            out.push_str(&format!(
+                "{},{},{},{}\n",
+                record.id,
+                record
+                    .parent_id
+                    .map(|parent_id| parent_id.to_string())
+                    .unwrap_or_default(),
+                csv_escape(&record.title),
+                record.done
+            ));
+        }
+
+        out
+    }
+
+    /// Load a tree from CSV as written by `to_csv`.
+    ///
+    /// Like `from_jsonl`, rows may come in any order and siblings keep the
+    /// order they appear in. Every `parent_id` must name a row in the file,
+    /// the parent links may not form a cycle, and no title may be blank.
+    pub fn from_csv(input: &str) -> Result<Self, String>
+    where
+        T: Default,
+    {
+        let mut rows = parse_csv(input)?.into_iter();
+        match rows.next() {
+            Some((_, header)) if header == ["id", "parent_id", "title", "done"] => {}
+            _ => return Err("expected the header 'id,parent_id,title,done'".into()),
+        }
+
+        // Pass 1: collect every row
+        let mut records: Vec<(usize, u32, Option<u32>, bool, String)> = Vec::new();
+        let mut seen = HashSet::new();
+        for (line_no, fields) in rows {
+            let [id, parent, title, done] = <[String; 4]>::try_from(fields).map_err(|fields| {
+                format!("line {}: expected 4 fields, got {}", line_no, fields.len())
+            })?;
+
+            let id: u32 = id
+                .trim()
+                .parse()
+                .map_err(|_| format!("line {}: invalid id '{}'", line_no, id))?;
+            let parent =
+                match parent.trim() {
+                    "" => None,
+                    parent => Some(parent.parse().map_err(|_| {
+                        format!("line {}: invalid parent_id '{}'", line_no, parent)
+                    })?),
+                };
+            let done = match done.trim() {
+                "true" => true,
+                "false" => false,
+                other => return Err(format!("line {}: invalid done '{}'", line_no, other)),
+            };
+
+            if !seen.insert(id) {
+                return Err(format!("line {}: duplicate id {}", line_no, id));
+            }
+            records.push((line_no, id, parent, done, title));
+        }
+
+        Self::from_parent_records(records)
+    }
+
+    /// Render as a Graphviz `digraph`: one node per tree node labelled
+    /// `title (id: N)`, one edge per parent -> child link. Done nodes are
+    /// filled green. Quotes, backslashes and newlines in titles are escaped.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph tree {\n    node [shape=box];\n");
+
+        let records = self.to_flat_records();
+        for record in &records {
+            let style = if record.done {
+                ", style=filled, fillcolor=palegreen"
+            } else {
+                ""
+            };
+            out.push_str(&format!(
+                "    n{} [label=\"{} (id: {})\"{}];\n",
+                record.id,
+                dot_escape(&record.title),
+                record.id,
+                style
+            ));
+        }
+        for record in &records {
+            if let Some(parent_id) = record.parent_id {
+                out.push_str(&format!("    n{} -> n{};\n", parent_id, record.id));
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    /// Render as a Mermaid `graph TD` flowchart. Node ids are prefixed with
+    /// `n` so they can't collide with Mermaid keywords (`end`, `graph`, ...);
+    /// labels are quoted with markup characters replaced by entity codes.
+    /// Done nodes get the `done` class.
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("graph TD\n");
+
+        let records = self.to_flat_records();
+        // Declare every node first so childless roots still show up
+        for record in &records {
+            out.push_str(&format!(
+                "    n{}[\"{}\"]\n",
+                record.id,
+                mermaid_escape(&record.title)
+            ));
+        }
+        for record in &records {
+            if let Some(parent_id) = record.parent_id {
+                out.push_str(&format!("    n{} --> n{}\n", parent_id, record.id));
+            }
+        }
+
+        let done: Vec<String> = records
+            .iter()
+            .filter(|record| record.done)
+            .map(|record| format!("n{}", record.id))
+            .collect();
+        if !done.is_empty() {
+            out.push_str("    classDef done fill:#c8f7c5,stroke:#2e7d32,color:#555\n");
+            out.push_str(&format!("    class {} done\n", done.join(",")));
+        }
+
+        out
+    }
+
+    /// Render a simple SVG diagram: one row per node in pre-order, indented
+    /// by depth, with elbow connectors to the parent. Done nodes get a green
+    /// marker and grey label. Deterministic, no layout engine.
+    pub fn to_svg(&self) -> String {
+        const ROW: usize = 24;
+        const INDENT: usize = 24;
+        const MARGIN: usize = 16;
+        const CHAR_WIDTH: usize = 8; // Rough average for a 14px sans-serif font
+
+        let records = self.to_flat_records();
+        let mut row_of: HashMap<u32, usize> = HashMap::new();
+        let mut body = String::new();
+        let mut width = 2 * MARGIN;
+
+        for (row, record) in records.iter().enumerate() {
+            row_of.insert(record.id, row);
+            let x = MARGIN + record.depth * INDENT;
+            let y = MARGIN + row * ROW + ROW / 2;
+
+            if let Some(parent_row) = record
+                .parent_id
+                .and_then(|parent_id| row_of.get(&parent_id))
+            {
+                let parent_x = x - INDENT;
+                let parent_y = MARGIN + parent_row * ROW + ROW / 2;
+                // Elbow: down from under the parent's marker, then across
+                body.push_str(&format!(
+                    "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#999\"/>\n",
+                    parent_x,
+                    parent_y + 5,
+                    parent_x,
+                    y
+                ));
+                body.push_str(&format!(
+                    "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#999\"/>\n",
+                    parent_x,
+                    y,
+                    x - 5,
+                    y
+                ));
+            }
+
+            let (fill, text_fill) = if record.done {
+                ("#4caf50", "#777")
+            } else {
+                ("#fff", "#000")
+            };
+            body.push_str(&format!(
+                "  <circle cx=\"{}\" cy=\"{}\" r=\"5\" fill=\"{}\" stroke=\"#333\"/>\n",
+                x, y, fill
+            ));
+            body.push_str(&format!(
+                "  <text x=\"{}\" y=\"{}\" fill=\"{}\">{}</text>\n",
+                x + 10,
+                y + 5,
+                text_fill,
+                xml_escape(&record.title)
+            ));
+
+            width = width.max(x + 10 + record.title.chars().count() * CHAR_WIDTH + MARGIN);
+        }
+
+        let height = 2 * MARGIN + records.len() * ROW;
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {w} {h}\" width=\"{w}\" height=\"{h}\" \
+             font-family=\"sans-serif\" font-size=\"14\">\n{body}</svg>\n",
+            w = width,
+            h = height,
+            body = body
+        )
+    }
+
+    /// Render as nested GitHub task-list items (`- [x]` / `- [ ]`), two
+    /// spaces of indentation per level like `fmt_node`, each ending in
+    /// `(id: N)`. An empty tree gives an empty string.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        for record in self.to_flat_records() {
+            out.push_str(&"  ".repeat(record.depth));
+            out.push_str(&format!(
+                "- [{}] {} (id: {})\n",
+                if record.done { "x" } else { " " },
+                record.title,
+                record.id
+            ));
+        }
+
+        out
+    }
+
+    /// Parse a nested GitHub task list back into a tree.
+    ///
+    /// Each non-blank line must be `- [ ] title` or `- [x] title`, indented by
+    /// two spaces per level under its parent; anything else is an error naming
+    /// the line number. A trailing `(id: N)` (as written by `to_markdown`)
+    /// is dropped and fresh ids are allocated. Done flags are taken
+    /// from the checkboxes as written.
+    pub fn from_markdown(md: &str) -> Result<Self, String>
+    where
+        T: Default,
+    {
+        Self::from_indented(md, |content| {
+            let (done, rest) = if let Some(rest) = content.strip_prefix("- [ ] ") {
+                (false, rest)
+            } else if let Some(rest) = content
+                .strip_prefix("- [x] ")
+                .or_else(|| content.strip_prefix("- [X] "))
+            {
+                (true, rest)
+            } else {
+                return Err("expected a '- [ ]' or '- [x]' task item".into());
+            };
+
+            Ok((done, 0, rest))
+        })
+    }
+
+    /// Parse a plain indented outline, as printed by `show` (`Display`),
+    /// back into a tree.
+    ///
+    /// Lines are indented by two spaces per level under their parent. A
+    /// leading `[x]` marks a node done; `[ ]`, `[~]` or no checkbox leaves it
+    /// undone. A `[!N]` priority marker and a trailing `(id: N)` are
+    /// understood, and fresh ids are allocated.
+    pub fn from_outline(text: &str) -> Result<Self, String>
+    where
+        T: Default,
+    {
+        Self::from_indented(text, |content| {
+            let (done, rest) = if let Some(rest) = content
+                .strip_prefix("[x] ")
+                .or_else(|| content.strip_prefix("[X] "))
+            {
+                (true, rest)
+            } else if let Some(rest) = content
+                .strip_prefix("[ ] ")
+                .or_else(|| content.strip_prefix("[~] "))
+            {
+                (false, rest)
+            } else {
+                (false, content)
+            };
+
+            let (priority, rest) = match rest
+                .strip_prefix("[!")
+                .and_then(|marked| marked.split_once("] "))
+            {
+                Some((level, rest)) => match level.parse() {
+                    Ok(priority) => (priority, rest),
+                    Err(_) => return Err(format!("invalid priority '[!{}]'", level)),
+                },
+                None => (0, rest),
+            };
+
+            Ok((done, priority, rest))
+        })
+    }
+
+    /// Shared core of the indented-text importers: two spaces per level,
+    /// blank lines skipped, and `parse_item` turning the rest of each line
+    /// into `(done, priority, title)`. Errors name the line number.
+    fn from_indented(
+        text: &str,
+        parse_item: impl Fn(&str) -> Result<(bool, u8, &str), String>,
+    ) -> Result<Self, String>
+    where
+        T: Default,
+    {
+        let mut tree = Tree::default();
+        let mut path: Vec<NodeRef<T>> = Vec::new(); // Last node seen at each depth
+
+        for (line_no, line) in text.lines().enumerate() {
+            let line_no = line_no + 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let content = line.trim_start_matches(' ');
+            let spaces = line.len() - content.len();
+            if spaces % 2 != 0 {
+                return Err(format!(
+                    "line {}: indentation must be a multiple of two spaces",
+                    line_no
+                ));
+            }
+            let depth = spaces / 2;
+            if depth > path.len() {
+                return Err(format!(
+                    "line {}: indented more than one level past its parent",
+                    line_no
+                ));
+            }
+
+            let (done, priority, rest) =
+                parse_item(content).map_err(|e| format!("line {}: {}", line_no, e))?;
+            let title = strip_id_suffix(rest).trim();
+            if title.is_empty() {
+                return Err(format!("line {}: title cannot be empty", line_no));
+            }
+
+            let node = Node::new(tree.alloc_id(), title, None);
+            {
+                let mut node = node.borrow_mut();
+                node.done = done;
+                node.priority = priority;
+            }
+
+            path.truncate(depth);
+            tree.attach_raw(&node, path.last());
+            path.push(node);
+        }
+
+        Ok(tree)
+    }
+
+    /// Export as an OPML 2.0 outline: one nested `<outline text="...">` per
+    /// node, with `_status="done"` on done nodes.
+    pub fn to_opml(&self) -> String {
+        let mut out = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <opml version=\"2.0\">\n  <head>\n    <title>treedesk</title>\n  </head>\n  <body>\n",
+        );
+
+        // `Some(node)` opens a node; `None` closes the innermost open one
+        let mut stack: Vec<(Option<NodeRef<T>>, usize)> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|root_ref| (Some(Rc::clone(root_ref)), 2))
+            .collect();
+        while let Some((entry, level)) = stack.pop() {
+            let indent = "  ".repeat(level);
+            let Some(node_ref) = entry else {
+                out.push_str(&format!("{}</outline>\n", indent));
+                continue;
+            };
+
+            let node = node_ref.borrow();
+            out.push_str(&format!(
+                "{}<outline text=\"{}\"",
+                indent,
+                xml_escape(&node.title)
+            ));
+            if node.done {
+                out.push_str(" _status=\"done\"");
+            }
+            if node.children.is_empty() {
+                out.push_str("/>\n");
+                continue;
+            }
+
+            out.push_str(">\n");
+            stack.push((None, level));
+            for child in node.children.iter().rev() {
+                stack.push((Some(Rc::clone(child)), level + 1));
+            }
+        }
+
+        out.push_str("  </body>\n</opml>\n");
+        out
+    }
+
+    /// Parse an OPML document: each `<outline>` under `<body>` becomes a node
+    /// titled by its `text` attribute, with fresh ids. A node is done if it
+    /// has `_status="done"` (or Workflowy's `_complete="true"`).
+    pub fn from_opml(xml: &str) -> Result<Self, String>
+    where
+        T: Default,
+    {
+        let doc = xml::parse(xml)?;
+        if doc.name != "opml" {
+            return Err(format!("expected an <opml> document, found <{}>", doc.name));
+        }
+        let body = doc.child("body").ok_or("missing <body> element")?;
+
+        let mut tree = Tree::default();
+        let mut stack: Vec<(&xml::Element, Option<NodeRef<T>>)> = body
+            .children
+            .iter()
+            .rev()
+            .map(|outline| (outline, None))
+            .collect();
+        while let Some((outline, parent)) = stack.pop() {
+            if outline.name != "outline" {
+                continue;
+            }
+
+            let title = outline
+                .attr("text")
+                .map(str::trim)
+                .filter(|title| !title.is_empty())
+                .ok_or("<outline> without a non-empty \"text\" attribute")?;
+            let done = outline.attr("_status") == Some("done")
+                || outline.attr("_complete") == Some("true");
+
+            let node = Node::new(tree.alloc_id(), title, None);
+            node.borrow_mut().done = done;
+            tree.attach_raw(&node, parent.as_ref());
+
+            for child in outline.children.iter().rev() {
+                stack.push((child, Some(Rc::clone(&node))));
+            }
+        }
+
+        Ok(tree)
+    }
+
+    /// Root-to-node title path joined with ` / `, for status bars.
+    /// Returns `None` if the id is not found.
+    ///
+    /// If the path is longer than `max_len` characters, segments are dropped
+    /// from the middle and replaced by `…`, keeping the root when it fits
+    /// (`Root / … / Parent / Node`). The last segment is always shown in
+    /// full, even if that alone exceeds `max_len`.
+    pub fn breadcrumb(&self, id: u32, max_len: usize) -> Option<String> {
+        let titles: Vec<String> = self
+            .lineage(id)?
+            .iter()
+            .map(|node_ref| node_ref.borrow().title.clone())
+            .collect();
+
+        let full = titles.join(" / ");
+        if full.chars().count() <= max_len || titles.len() == 1 {
+            return Some(full);
+        }
+
+        // Keep the root plus as many trailing segments as fit
+        let last = titles.len() - 1;
+        for first_kept in 2..=last {
+            let mut parts = vec![titles[0].as_str(), "…"];
+            parts.extend(titles[first_kept..].iter().map(String::as_str));
+            let candidate = parts.join(" / ");
+            if candidate.chars().count() <= max_len {
+                return Some(candidate);
+            }
+        }
+
+        Some(format!("… / {}", titles[last]))
+    }
+
+    /// Every done leaf in pre-order, with its full breadcrumb-qualified title
+    /// (e.g. `Project / Backend / Auth`). Handy for weekly reviews.
+    pub fn completed_flat(&self) -> Vec<(u32, String)> {
+        self.preorder()
+            .iter()
+            .map(|node_ref| node_ref.borrow())
+            .filter(|node| node.done && node.children.is_empty())
+            .filter_map(|node| Some((node.id, self.breadcrumb(node.id, usize::MAX)?)))
+            .collect()
+    }
+
+    /// Id of the sibling right after `id` (among its parent's children, or
+    /// among the roots), or `None` at the end or if not found.
+    pub fn next_sibling(&self, id: u32) -> Option<u32> {
+        self.sibling_at(id, 1)
+    }
+
+    /// Id of the sibling right before `id`, or `None` at the start or if not found.
+    pub fn prev_sibling(&self, id: u32) -> Option<u32> {
+        self.sibling_at(id, -1)
+    }
+
+    /// Ids of `id`'s ancestors, nearest parent first. Empty for a root or
+    /// an unknown id.
+    pub fn ancestors(&self, id: u32) -> Vec<u32> {
+        match self.lineage(id) {
+            Some(lineage) => lineage
+                .iter()
+                .rev()
+                .skip(1)
+                .map(|node_ref| node_ref.borrow().id)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Ids of the other children of `id`'s parent (or the other roots, for a
+    /// root), in stored order. Empty for an unknown id.
+    pub fn siblings(&self, id: u32) -> Vec<u32> {
+        self.with_siblings(id, |siblings, pos| {
+            siblings
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != pos)
+                .map(|(_, sibling)| sibling.borrow().id)
+                .collect()
+        })
+        .unwrap_or_default()
+    }
+
+    /// How many levels below its root a node sits (roots are 0), found by
+    /// walking up the parent links. `None` if not found.
+    pub fn depth(&self, id: u32) -> Option<usize> {
+        let mut depth = 0;
+
+        let mut current = self.index.get(&id)?.borrow().parent.clone();
+        while let Some(parent) = current.and_then(|parent| parent.upgrade()) {
+            depth += 1;
+            current = parent.borrow().parent.clone();
+        }
+
+        Some(depth)
+    }
+
+    /// Length of the longest downward path from a node to a leaf (leaves
+    /// are 0). `None` if not found.
+    pub fn height(&self, id: u32) -> Option<usize> {
+        let mut height = 0;
+
+        let mut stack = vec![(Rc::clone(self.index.get(&id)?), 0)];
+        while let Some((node_ref, level)) = stack.pop() {
+            height = height.max(level);
+            for child in &node_ref.borrow().children {
+                stack.push((Rc::clone(child), level + 1));
+            }
+        }
+
+        Some(height)
+    }
+
+    /// Number of nodes completed on each UTC day (`YYYY-MM-DD`), oldest first.
+    /// Nodes without a completion time are skipped.
+    pub fn completions_per_day(&self) -> Vec<(String, usize)> {
+        let mut counts: HashMap<Date, usize> = HashMap::new();
+        for node_ref in self.index.values() {
+            if let Some(completed_at) = node_ref.borrow().completed_at {
+                *counts
+                    .entry(Date::from_system_time(completed_at))
+                    .or_insert(0) += 1;
+            }
+        }
+
+        let mut days: Vec<(Date, usize)> = counts.into_iter().collect();
+        days.sort();
+        days.into_iter()
+            .map(|(date, count)| (date.to_string(), count))
+            .collect()
+    }
+
+    /// Cross-check parent pointers against child lists, returning one message
+    /// per problem (empty if all links are consistent).
+    ///
+    /// Flags dangling parent pointers, parents that don't list the node as a
+    /// child, children whose parent pointer points elsewhere, and parentless
+    /// nodes missing from `roots`.
+    pub fn weak_link_report(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        let mut ids: Vec<u32> = self.index.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            let node_ref = &self.index[&id];
+            let node = node_ref.borrow();
+
+            match &node.parent {
+                Some(parent_weak) => match parent_weak.upgrade() {
+                    None => problems.push(format!("node {}: parent pointer is dangling", id)),
+                    Some(parent_rc) => {
+                        let parent = parent_rc.borrow();
+                        if !parent
+                            .children
+                            .iter()
+                            .any(|child| Rc::ptr_eq(child, node_ref))
+                        {
+                            problems.push(format!(
+                                "node {}: parent {} does not list it as a child",
+                                id, parent.id
+                            ));
+                        }
+                    }
+                },
+                None => {
+                    if !self.roots.iter().any(|root| Rc::ptr_eq(root, node_ref)) {
+                        problems.push(format!("node {}: has no parent but is not a root", id));
+                    }
+                }
+            }
+
+            for child_ref in &node.children {
+                let points_back = child_ref
+                    .borrow()
+                    .parent
+                    .as_ref()
+                    .and_then(|parent| parent.upgrade())
+                    .is_some_and(|parent| Rc::ptr_eq(&parent, node_ref));
+                if !points_back {
+                    problems.push(format!(
+                        "node {}: listed under {} but its parent pointer points elsewhere",
+                        child_ref.borrow().id,
+                        id
+                    ));
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Full integrity check: every node reachable from `roots` is indexed
+    /// under its own id and vice versa, nothing is reachable twice (which
+    /// would mean a cycle or a shared child), parent links agree with child
+    /// lists (see `weak_link_report`), and `next_id` is above every live id.
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+
+        let mut seen: HashSet<*const RefCell<Node<T>>> = HashSet::new();
+        let mut reachable_ids = HashSet::new();
+        let mut stack: Vec<NodeRef<T>> = self.roots.iter().rev().cloned().collect();
+        while let Some(node_ref) = stack.pop() {
+            let node = node_ref.borrow();
+            if !seen.insert(Rc::as_ptr(&node_ref)) {
+                // Don't descend again, or a cycle would never terminate
+                problems.push(format!(
+                    "node {}: reachable more than once (cycle or shared child)",
+                    node.id
+                ));
+                continue;
+            }
+            reachable_ids.insert(node.id);
+
+            match self.index.get(&node.id) {
+                Some(indexed) if Rc::ptr_eq(indexed, &node_ref) => {}
+                Some(_) => problems.push(format!(
+                    "node {}: index entry points to a different node",
+                    node.id
+                )),
+                None => problems.push(format!("node {}: reachable but not in the index", node.id)),
+            }
+            if node.id >= self.next_id {
+                problems.push(format!(
+                    "node {}: id is not below next_id ({})",
+                    node.id, self.next_id
+                ));
+            }
+
+            stack.extend(node.children.iter().rev().cloned());
+        }
+
+        let mut ids: Vec<u32> = self.index.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            let node_id = self.index[&id].borrow().id;
+            if node_id != id {
+                problems.push(format!("index key {} holds node {}", id, node_id));
+            } else if !reachable_ids.contains(&id) {
+                problems.push(format!(
+                    "node {}: indexed but not reachable from the roots",
+                    id
+                ));
+            }
+        }
+
+        problems.extend(self.weak_link_report());
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Fraction (0.0 to 1.0) of the leaves under `id` that are done; a leaf
+    /// itself counts as 1.0 or 0.0. Returns `None` if not found.
+    ///
+    /// Counts leaves directly, independently of the propagated parent flags.
+    pub fn progress(&self, id: u32) -> Option<f32> {
+        let mut leaves = 0usize;
+        let mut done = 0usize;
+
+        let mut stack = vec![self.index.get(&id)?.clone()];
+        while let Some(node_ref) = stack.pop() {
+            let node = node_ref.borrow();
+            if node.children.is_empty() {
+                leaves += 1;
+                if node.done {
+                    done += 1;
+                }
+            }
+            stack.extend(node.children.iter().cloned());
+        }
+
+        Some(done as f32 / leaves as f32)
+    }
+
+    /// Like `progress`, but each leaf counts by its `weight`, so one big
+    /// task can outweigh several small ones. Only leaf weights matter. If
+    /// the leaves weigh nothing in total, this is the same as `progress`.
+    pub fn weighted_progress(&self, id: u32) -> Option<f32> {
+        let mut total = 0u64;
+        let mut done = 0u64;
+
+        let mut stack = vec![self.index.get(&id)?.clone()];
+        while let Some(node_ref) = stack.pop() {
+            let node = node_ref.borrow();
+            if node.children.is_empty() {
+                total += node.weight as u64;
+                if node.done {
+                    done += node.weight as u64;
+                }
+            }
+            stack.extend(node.children.iter().cloned());
+        }
+
+        if total == 0 {
+            return self.progress(id);
+        }
+        Some((done as f64 / total as f64) as f32)
+    }
+
+    /// Highlight `id` and its ancestors in the pretty output, or clear with `None`.
+    /// Returns `false` if the id is not found.
+    pub fn set_highlight(&mut self, id: Option<u32>) -> bool {
+        if let Some(id) = id
+            && !self.index.contains_key(&id)
+        {
+            return false;
+        }
+
+        self.highlighted = id;
+        true
+    }
+
+    /// Print the tree to the given formatter
+    pub fn fmt_pretty(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_pretty(f)
+    }
+
+    /// Write the pretty tree straight to `w` (file, stdout lock, socket, ...)
+    /// without building the whole output as a `String` first.
+    pub fn render<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut adapter = IoAdapter {
+            inner: w,
+            error: None,
+        };
+
+        self.write_pretty(&mut adapter).map_err(|_| {
+            adapter
+                .error
+                .take()
+                .unwrap_or_else(|| io::Error::other("formatting failed"))
+        })
+    }
+
+    /// Alias for `render`.
+    pub fn write_to<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+        self.render(w)
+    }
+
+    /// The subtree rooted at `id`, formatted like `fmt_pretty` but starting
+    /// at indent 0. Returns `None` if not found.
+    pub fn subtree_string(&self, id: u32) -> Option<String> {
+        let node_ref = self.index.get(&id)?;
+
+        let mut out = String::new();
+        let marked = self.highlight_path();
+        // Writing to a String can't fail
+        let _ = Self::fmt_node(node_ref, 0, &marked, &RenderOptions::default(), &mut out);
+
+        Some(out)
+    }
+
+    /// Like `fmt_pretty`, but stop after `max_depth` levels below the roots
+    /// (0 = roots only). A `...` line marks where children were left out.
+    pub fn fmt_pretty_limited(&self, max_depth: usize, f: &mut impl fmt::Write) -> fmt::Result {
+        let options = RenderOptions {
+            max_depth: Some(max_depth),
+            ..RenderOptions::default()
+        };
+        self.write_pretty_with(&options, f)
+    }
+
+    /// Like `fmt_pretty`, drawn in the given style. `DisplayStyle::Plain` is
+    /// exactly the `Display` output; `Unicode` adds box-drawing guide lines.
+    pub fn fmt_styled(&self, style: DisplayStyle, f: &mut impl fmt::Write) -> fmt::Result {
+        let options = RenderOptions {
+            style,
+            ..RenderOptions::default()
+        };
+        self.write_pretty_with(&options, f)
+    }
+
+    /// Like `fmt_styled`, with done nodes in green via ANSI escape codes.
+    /// Only meant for terminals; callers should check before using it.
+    pub fn fmt_colored(&self, style: DisplayStyle, f: &mut impl fmt::Write) -> fmt::Result {
+        let options = RenderOptions {
+            style,
+            color: true,
+            ..RenderOptions::default()
+        };
+        self.write_pretty_with(&options, f)
+    }
+
+    /// Like `fmt_pretty`, but only nodes that pass `keep` or have a
+    /// descendant that does. Ancestors of a match stay for context, so the
+    /// output is still a well-formed tree.
+    pub fn fmt_filtered(
+        &self,
+        keep: impl Fn(&Node<T>) -> bool,
+        f: &mut impl fmt::Write,
+    ) -> fmt::Result {
+        // Pre-order reversed visits every child before its parent
+        let mut order = Vec::new();
+        let mut stack: Vec<NodeRef<T>> = self.roots.iter().rev().cloned().collect();
+        while let Some(node_ref) = stack.pop() {
+            stack.extend(node_ref.borrow().children.iter().rev().cloned());
+            order.push(node_ref);
+        }
+
+        let mut visible = HashSet::new();
+        for node_ref in order.iter().rev() {
+            let node = node_ref.borrow();
+            if keep(&node)
+                || node
+                    .children
+                    .iter()
+                    .any(|child| visible.contains(&child.borrow().id))
+            {
+                visible.insert(node.id);
+            }
+        }
+
+        let options = RenderOptions {
+            visible: Some(&visible),
+            ..RenderOptions::default()
+        };
+        self.write_pretty_with(&options, f)
+    }
+
+    /// Shared core of `fmt_pretty` and `render`
+    fn write_pretty(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        self.write_pretty_with(&RenderOptions::default(), out)
+    }
+
+    fn write_pretty_with(&self, options: &RenderOptions, out: &mut impl fmt::Write) -> fmt::Result {
+        let marked = self.highlight_path();
+        for root in &self.roots {
+            if options.shows(root.borrow().id) {
+                Self::fmt_node(root, 0, &marked, options, out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Render the tree with single-child chains joined onto one line,
+    /// e.g. `[ ] A > B > C (id: 3)`. The checkbox and id are those of the
+    /// deepest node in the chain; branching nodes render indented as usual.
+    pub fn to_compact_string(&self) -> String {
+        let mut out = String::new();
+        let partial: HashSet<u32> = self.roots.iter().flat_map(Self::partially_done).collect();
+
+        let mut stack: Vec<(NodeRef<T>, usize)> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|root_ref| (Rc::clone(root_ref), 0))
+            .collect();
+        while let Some((node_ref, indent)) = stack.pop() {
+            // Follow the chain while each node has exactly one child
+            let mut titles = vec![node_ref.borrow().title.clone()];
+            let mut last = node_ref;
+            loop {
+                let only_child = {
+                    let node = last.borrow();
+                    if node.children.len() == 1 {
+                        Some(Rc::clone(&node.children[0]))
+                    } else {
+                        None
+                    }
+                };
+                let Some(child) = only_child else {
+                    break;
+                };
+                titles.push(child.borrow().title.clone());
+                last = child;
+            }
+
+            let node = last.borrow();
+            out.push_str(&"  ".repeat(indent));
+            out.push_str(&format!(
+                "[{}] {} (id: {})\n",
+                checkbox(node.done, partial.contains(&node.id)),
+                titles.join(" > "),
+                node.id
+            ));
+
+            for child in node.children.iter().rev() {
+                stack.push((Rc::clone(child), indent + 1));
+            }
+        }
+
+        out
+    }
+
+    /// `marked` holds the ids on the highlighted path. When it is non-empty,
+    /// every line gets a two-column gutter so marked lines stay aligned.
+    /// `options.max_depth` counts levels below the node this call starts at.
+    fn fmt_node(
+        node_ref: &NodeRef<T>,
+        indent: usize,
+        marked: &HashSet<u32>,
+        options: &RenderOptions,
+        f: &mut impl fmt::Write,
+    ) -> fmt::Result {
+        let RenderOptions {
+            max_depth,
+            style,
+            color,
+            ..
+        } = *options;
+        let partial = Self::partially_done(node_ref);
+
+        // Each entry carries its guide (ancestor columns) and its own
+        // connector; `Plain` has no connectors, just two spaces per level.
+        // Explicit stack so very deep trees can't overflow the call stack
+        let mut stack = vec![(Rc::clone(node_ref), max_depth, "  ".repeat(indent), "")];
+        while let Some((node_ref, max_depth, guide, connector)) = stack.pop() {
+            let node = node_ref.borrow();
+
+            // highlight gutter
+            if !marked.is_empty() {
+                write!(
+                    f,
+                    "{}",
+                    if marked.contains(&node.id) {
+                        "» "
+                    } else {
+                        "  "
+                    }
+                )?;
+            }
+
+            // indentation
+            write!(f, "{}{}", guide, connector)?;
+
+            let colored = color && node.done;
+            if colored {
+                write!(f, "{}", ANSI_GREEN)?;
+            }
+            write!(f, "[{}] ", checkbox(node.done, partial.contains(&node.id)))?;
+            if node.priority > 0 {
+                write!(f, "[!{}] ", node.priority)?;
+            }
+            write!(f, "{} (id: {})", node.title, node.id)?;
+            if colored {
+                write!(f, "{}", ANSI_RESET)?;
+            }
+            writeln!(f)?;
+
+            let children: Vec<&NodeRef<T>> = node
+                .children
+                .iter()
+                .filter(|child| options.shows(child.borrow().id))
+                .collect();
+            if children.is_empty() {
+                continue;
+            }
+
+            // Below a last child the column is empty; otherwise it continues
+            let child_guide = match (style, connector) {
+                (DisplayStyle::Plain, _) => format!("{}  ", guide),
+                (DisplayStyle::Unicode, "") => guide.clone(),
+                (DisplayStyle::Unicode, "└── ") => format!("{}    ", guide),
+                (DisplayStyle::Unicode, _) => format!("{}│   ", guide),
+            };
+            let connector_for = |last: bool| match style {
+                DisplayStyle::Plain => "",
+                DisplayStyle::Unicode if last => "└── ",
+                DisplayStyle::Unicode => "├── ",
+            };
+
+            match max_depth {
+                Some(0) => {
+                    // children suppressed
+                    if !marked.is_empty() {
+                        write!(f, "  ")?;
+                    }
+                    writeln!(f, "{}{}...", child_guide, connector_for(true))?;
+                }
+                _ => {
+                    let child_depth = max_depth.map(|depth| depth - 1);
+                    let last = children.len() - 1;
+                    for (i, child) in children.into_iter().enumerate().rev() {
+                        stack.push((
+                            Rc::clone(child),
+                            child_depth,
+                            child_guide.clone(),
+                            connector_for(i == last),
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ids of the highlighted node and all its ancestors (empty if none set)
+    fn highlight_path(&self) -> HashSet<u32> {
+        match self.highlighted.filter(|id| self.index.contains_key(id)) {
+            Some(id) => std::iter::once(id).chain(self.ancestors(id)).collect(),
+            None => HashSet::new(),
+        }
+    }
+
+    /// Allocate a new unique ID
+    fn alloc_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        id
+    }
+
+    /// Remove this node and all descendants from the `index` map
+    fn remove_from_index_rec(&mut self, node_ref: &NodeRef<T>) -> Vec<(u32, String)> {
+        let mut titles = Vec::new();
+        let mut stack = vec![Rc::clone(node_ref)];
+        while let Some(node_ref) = stack.pop() {
+            let node = node_ref.borrow();
+            self.index.remove(&node.id);
+            if let Some(title) = self.last_titles.remove(&node.id) {
+                titles.push((node.id, title));
+            }
+            stack.extend(node.children.iter().cloned());
+        }
+        titles
+    }
+
+    /// The nodes from the root down to `id` (inclusive), or `None` if not found
+    fn lineage(&self, id: u32) -> Option<Vec<NodeRef<T>>> {
+        let mut chain = Vec::new();
+
+        let mut current = Some(self.index.get(&id)?.clone());
+        while let Some(node_ref) = current {
+            current = node_ref
+                .borrow()
+                .parent
+                .as_ref()
+                .and_then(|parent| parent.upgrade());
+            chain.push(node_ref);
+        }
+
+        chain.reverse();
+        Some(chain)
+    }
+
+    /// Position of `id` in the list it lives in (its parent's children, or
+    /// the roots), or `None` if not found.
+    fn sibling_position(&self, id: u32) -> Option<usize> {
+        self.with_siblings(id, |_, pos| pos)
+    }
+
+    /// Call `f` with a borrow of the list `id` lives in and its position in
+    /// it, without cloning the list. `None` if not found.
+    fn with_siblings<R>(&self, id: u32, f: impl FnOnce(&[NodeRef<T>], usize) -> R) -> Option<R> {
+        let node_ref = self.index.get(&id)?;
+        let parent = node_ref
+            .borrow()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade());
+        let position = |siblings: &[NodeRef<T>]| {
+            siblings
+                .iter()
+                .position(|sibling| Rc::ptr_eq(sibling, node_ref))
+        };
+
+        match parent {
+            Some(parent) => {
+                let parent = parent.borrow();
+                let pos = position(&parent.children)?;
+                Some(f(&parent.children, pos))
+            }
+            None => {
+                let pos = position(&self.roots)?;
+                Some(f(&self.roots, pos))
+            }
+        }
+    }
+
+    /// Id of the node at `offset` places from `id` in its sibling list, or
+    /// `None` if that falls outside the list or `id` is not found.
+    fn sibling_at(&self, id: u32, offset: isize) -> Option<u32> {
+        self.with_siblings(id, |siblings, pos| {
+            let other = pos.checked_add_signed(offset)?;
+            siblings.get(other).map(|sibling| sibling.borrow().id)
+        })?
+    }
+
+    /// All nodes in pre-order (roots in order, children in stored order)
+    fn preorder(&self) -> Vec<NodeRef<T>> {
+        self.iter_dfs().collect()
+    }
+
+    /// Build an unlinked, unindexed copy of a subtree, taking each copy's id
+    /// from `new_id(original_id)` (called in pre-order). All other fields,
+    /// timestamps included, are kept.
+    fn copy_subtree(source: &NodeRef<T>, mut new_id: impl FnMut(u32) -> u32) -> NodeRef<T>
+    where
+        T: Clone,
+    {
+        let copy_root = Self::copy_node(&source.borrow(), &mut new_id, None);
+
+        let mut stack: Vec<(NodeRef<T>, NodeRef<T>)> = source
+            .borrow()
+            .children
+            .iter()
+            .rev()
+            .map(|child| (Rc::clone(child), Rc::clone(&copy_root)))
+            .collect();
+        while let Some((original_ref, copy_parent)) = stack.pop() {
+            let original = original_ref.borrow();
+            let copy = Self::copy_node(&original, &mut new_id, Some(&copy_parent));
+            copy_parent.borrow_mut().children.push(Rc::clone(&copy));
+
+            for child in original.children.iter().rev() {
+                stack.push((Rc::clone(child), Rc::clone(&copy)));
+            }
+        }
+
+        copy_root
+    }
+
+    /// A childless copy of `original`, with its id taken from `new_id`
+    fn copy_node(
+        original: &Node<T>,
+        new_id: &mut impl FnMut(u32) -> u32,
+        parent: Option<&NodeRef<T>>,
+    ) -> NodeRef<T>
+    where
+        T: Clone,
+    {
+        let copy = Node::with_data(
+            new_id(original.id),
+            original.title.clone(),
+            original.data.clone(),
+            parent.map(Rc::downgrade),
+        );
+        {
+            let mut node = copy.borrow_mut();
+            node.done = original.done;
+            node.priority = original.priority;
+            node.weight = original.weight;
+            node.tags = original.tags.clone();
+            node.completed_at = original.completed_at;
+            node.due = original.due;
+            node.created_at = original.created_at;
+            node.modified_at = original.modified_at;
+        }
+
+        copy
+    }
+
+    /// Swap `id` with the sibling after it (`forward`) or before it, within
+    /// its parent's children or the roots
+    fn swap_with_sibling(&mut self, id: u32, forward: bool) -> bool {
+        let Some(pos) = self.sibling_position(id) else {
+            return false;
+        };
+        let Some(other_id) = self.sibling_at(id, if forward { 1 } else { -1 }) else {
+            return false;
+        };
+        let other = if forward { pos + 1 } else { pos - 1 };
+
+        let parent = self.index[&id]
+            .borrow()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade());
+        let parent_id = parent.as_ref().map(|parent| parent.borrow().id);
+        let before = self.child_order(parent_id);
+        match parent {
+            Some(parent) => parent.borrow_mut().children.swap(pos, other),
+            None => self.roots.swap(pos, other),
+        }
+
+        for id in [id, other_id] {
+            self.emit(TreeEvent::Moved {
+                id,
+                new_parent: parent_id,
+            });
+        }
+        self.record_reorder(parent_id, before);
+        true
+    }
+
+    /// Ids of the children of `parent_id` (or of the roots), in stored order.
+    /// Empty for an unknown id.
+    fn child_order(&self, parent_id: Option<u32>) -> Vec<u32> {
+        let ids = |list: &[NodeRef<T>]| list.iter().map(|node| node.borrow().id).collect();
+        match parent_id {
+            Some(id) => self
+                .index
+                .get(&id)
+                .map(|parent| ids(&parent.borrow().children))
+                .unwrap_or_default(),
+            None => ids(&self.roots),
+        }
+    }
+
+    /// Record a reorder of `parent_id`'s children (or the roots) from the
+    /// id order `before` to the current one, unless nothing moved
+    fn record_reorder(&mut self, parent_id: Option<u32>, before: Vec<u32>) {
+        let after = self.child_order(parent_id);
+        if after != before {
+            self.record(Edit::Reordered {
+                parent: parent_id,
+                before,
+                after,
+            });
+        }
+    }
+
+    /// Put the children of `parent_id` (or the roots) in the id order
+    /// `order`. Returns `false` if they are no longer exactly those nodes.
+    fn reorder_to(&mut self, parent_id: Option<u32>, order: &[u32]) -> bool {
+        let parent = match parent_id {
+            Some(id) => match self.index.get(&id).cloned() {
+                Some(parent) => Some(parent),
+                None => return false,
+            },
+            None => None,
+        };
+        let reorder = |list: &mut Vec<NodeRef<T>>| {
+            if list.len() != order.len() {
+                return false;
+            }
+            let mut by_id: HashMap<u32, NodeRef<T>> = list
+                .iter()
+                .map(|node| (node.borrow().id, Rc::clone(node)))
+                .collect();
+            match order.iter().map(|id| by_id.remove(id)).collect() {
+                Some(reordered) => {
+                    *list = reordered;
+                    true
+                }
+                None => false,
+            }
+        };
+
+        let reordered = match parent {
+            Some(parent) => reorder(&mut parent.borrow_mut().children),
+            None => reorder(&mut self.roots),
+        };
+        if reordered {
+            self.emit_reordered(parent_id);
+        }
+        reordered
+    }
+
+    /// Report every child of `parent_id` (or every root) as `Moved`, after
+    /// a sort that may have reordered them all
+    fn emit_reordered(&self, parent_id: Option<u32>) {
+        if self.listeners.borrow().is_empty() {
+            return;
+        }
 
-#[allow(dead_code)]
-impl Tree {
-    /// Create an empty tree
-    pub fn new() -> Self {
-        Tree {
-            roots: Vec::new(),
-            index: HashMap::new(),
-            next_id: 1,
+        let siblings = match parent_id.and_then(|id| self.index.get(&id)) {
+            Some(parent) => parent.borrow().children.clone(),
+            None => self.roots.clone(),
+        };
+        for node_ref in siblings {
+            let id = node_ref.borrow().id;
+            self.emit(TreeEvent::Moved {
+                id,
+                new_parent: parent_id,
+            });
         }
     }
 
-    /// Add a new root node. Returns its ID.
-    pub fn add_root(&mut self, title: impl Into<String>) -> u32 {
-        let id = self.alloc_id();
-        let node = Node::new(id, title, None);
+    fn sort_nodes(nodes: &mut [NodeRef<T>], by_title: bool) {
+        if by_title {
+            nodes.sort_by_cached_key(|node_ref| node_ref.borrow().title.to_lowercase());
+        } else {
+            nodes.sort_by_key(|node_ref| node_ref.borrow().id);
+        }
+    }
 
-        self.index.insert(id, Rc::clone(&node));
-        self.roots.push(node);
+    /// Ids in the subtree of `node_ref` that are not done but have both done
+    /// and undone descendants; display-only, the stored flags are untouched
+    fn partially_done(node_ref: &NodeRef<T>) -> HashSet<u32> {
+        let mut partial = HashSet::new();
 
-        id
+        // (any done, any undone) over each node's subtree, itself included;
+        // reverse pre-order fills in children before their parent
+        let mut seen: HashMap<u32, (bool, bool)> = HashMap::new();
+        for node_ref in Self::subtree_preorder(node_ref).iter().rev() {
+            let node = node_ref.borrow();
+            let (mut any_done, mut any_undone) = (false, false);
+            for child in &node.children {
+                let (done, undone) = seen[&child.borrow().id];
+                any_done |= done;
+                any_undone |= undone;
+            }
+
+            if !node.done && any_done && any_undone {
+                partial.insert(node.id);
+            }
+            seen.insert(node.id, (any_done || node.done, any_undone || !node.done));
+        }
+
+        partial
     }
 
-    /// Add a child under `parent_id`. Returns child ID on success
-    pub fn add_child(&mut self, parent_id: u32, title: impl Into<String>) -> Option<u32> {
-        let parent = self.index.get(&parent_id)?.clone();
+    /// `node_ref` and all its descendants in pre-order
+    fn subtree_preorder(node_ref: &NodeRef<T>) -> Vec<NodeRef<T>> {
+        let mut out = Vec::new();
 
-        let id = self.alloc_id();
-        let parent_weak = Rc::downgrade(&parent);
-        let child = Node::new(id, title, Some(parent_weak));
+        let mut stack = vec![Rc::clone(node_ref)];
+        while let Some(node_ref) = stack.pop() {
+            stack.extend(node_ref.borrow().children.iter().rev().cloned());
+            out.push(node_ref);
+        }
 
-        parent.borrow_mut().children.push(child.clone());
-        self.index.insert(id, child);
+        out
+    }
 
-        // recompute done flags upward from parent
-        Self::propagate_done_upward(&parent);
+    /// Pre-order ids of nodes whose lowercased title satisfies `pred`
+    fn find_by_title(&self, pred: impl Fn(&str) -> bool) -> Vec<u32> {
+        self.preorder()
+            .iter()
+            .map(|node_ref| node_ref.borrow())
+            .filter(|node| pred(&node.title.to_lowercase()))
+            .map(|node| node.id)
+            .collect()
+    }
 
-        Some(id)
+    /// Attach a freshly built node under `parent` (or as a root) and index it,
+    /// bumping `next_id` past its id. Done flags are left exactly as they are,
+    /// since importers restore them as stored.
+    fn attach_raw(&mut self, node: &NodeRef<T>, parent: Option<&NodeRef<T>>) {
+        let id = {
+            let mut n = node.borrow_mut();
+            n.parent = parent.map(Rc::downgrade);
+            n.id
+        };
+
+        match parent {
+            Some(parent) => parent.borrow_mut().children.push(Rc::clone(node)),
+            None => self.roots.push(Rc::clone(node)),
+        }
+        self.index.insert(id, Rc::clone(node));
+        self.next_id = self.next_id.max(id.saturating_add(1));
     }
 
-    /// Toggle the `done` flag for a node. Returns `true` if found.
+    /// Pass 2 of `from_jsonl` and `from_csv`: check the `(line_no, id,
+    /// parent, done, title)` records (ids already unique, titles not blank),
+    /// then create and link every node, siblings in record order.
     ///
-    /// After toggling, completion status is propagated upwards:
-    /// a parent becomes done if *all* its children are done.
-    pub fn toggle(&mut self, id: u32) -> bool {
-        if let Some(node) = self.index.get(&id).cloned() {
+    /// Parents are checked before anything is linked: a cycle of `Rc`
+    /// child links would never be freed.
+    fn from_parent_records(
+        records: Vec<(usize, u32, Option<u32>, bool, String)>,
+    ) -> Result<Self, String>
+    where
+        T: Default,
+    {
+        let parents: HashMap<u32, Option<u32>> = records
+            .iter()
+            .map(|(_, id, parent, _, _)| (*id, *parent))
+            .collect();
+        for (line_no, id, parent, _, title) in &records {
+            if title.trim().is_empty() {
+                return Err(format!("line {}: node {} has an empty title", line_no, id));
+            }
+            if let Some(parent) = parent
+                && !parents.contains_key(parent)
             {
-                // If the given id exists, toggle its `done` flag
-                let mut n = node.borrow_mut();
-                n.done = !n.done;
+                return Err(format!(
+                    "line {}: parent {} of node {} not found",
+                    line_no, parent, id
+                ));
             }
+        }
 
-            Self::propagate_done_upward(&node);
-            true
-        } else {
-            false
+        // Walk up from each node; reaching a node already on the current
+        // walk is a cycle, reaching one already known to lead to a root is not
+        let mut rooted: HashSet<u32> = HashSet::new();
+        for (_, id, _, _, _) in &records {
+            let mut walk = Vec::new();
+            let mut on_walk = HashSet::new();
+            let mut current = Some(*id);
+            while let Some(id) = current {
+                if rooted.contains(&id) {
+                    break;
+                }
+                if !on_walk.insert(id) {
+                    return Err("parent links form a cycle".into());
+                }
+                walk.push(id);
+                current = parents[&id];
+            }
+            rooted.extend(walk);
+        }
+
+        let mut nodes: HashMap<u32, NodeRef<T>> = HashMap::new();
+        for (_, id, _, done, title) in &records {
+            let node = Node::new(*id, title.clone(), None);
+            node.borrow_mut().done = *done;
+            nodes.insert(*id, node);
+        }
+
+        let mut tree = Tree::default();
+        for (_, id, parent, _, _) in &records {
+            let parent_ref = parent.map(|parent| &nodes[&parent]);
+            tree.attach_raw(&nodes[id], parent_ref);
         }
+
+        Ok(tree)
     }
 
-    /// Delete a node and its subtree. Returns `true` if found.
-    ///
-    /// - Detaches it from parent or roots
-    /// - Removes it and all descendants from the index
-    /// - Recomputes parent completion upwards
-    pub fn delete(&mut self, id: u32) -> bool {
-        let Some(node_ref) = self.index.get(&id).cloned() else {
-            return false;
-        };
+    /// Build one value per root from the leaves up: `make` gets each node
+    /// with the already-built values of its children, in order. Iterative,
+    /// so deep chains can't overflow the call stack.
+    fn fold_subtrees<R>(
+        roots: &[NodeRef<T>],
+        mut make: impl FnMut(&Node<T>, Vec<R>) -> R,
+    ) -> Vec<R> {
+        // Pre-order, then walked backwards: every subtree is finished before
+        // its parent, and a node's children are the top of `built`, first
+        // child uppermost
+        let mut order = Vec::new();
+        let mut stack: Vec<NodeRef<T>> = roots.iter().rev().cloned().collect();
+        while let Some(node_ref) = stack.pop() {
+            stack.extend(node_ref.borrow().children.iter().rev().cloned());
+            order.push(node_ref);
+        }
 
-        // 1. Detach from the parent or from roots
-        let parent_weak_opt = {
+        let mut built: Vec<R> = Vec::new();
+        for node_ref in order.iter().rev() {
             let node = node_ref.borrow();
-            node.parent.clone()
-        };
+            let children: Vec<R> = built
+                .drain(built.len() - node.children.len()..)
+                .rev()
+                .collect();
+            built.push(make(&node, children));
+        }
 
-        if let Some(parent_weak) = parent_weak_opt {
-            if let Some(parent_rc) = parent_weak.upgrade() {
-                {
-                    let mut parent = parent_rc.borrow_mut();
-                    parent
-                        .children
-                        .retain(|child_ref| child_ref.borrow().id != id);
-                }
+        built.reverse();
+        built
+    }
 
-                // Recompute done flags upward from parent
-                Self::propagate_done_upward(&parent_rc);
+    fn node_to_serializable(node: &Node<T>, children: Vec<SerializableNode>) -> SerializableNode {
+        SerializableNode {
+            id: node.id,
+            title: node.title.clone(),
+            done: node.done,
+            children,
+        }
+    }
+
+    fn node_to_json(node: &Node<T>, children: Vec<Value>) -> Value {
+        Value::Object(vec![
+            ("id".into(), Value::Number(node.id as f64)),
+            ("title".into(), Value::String(node.title.clone())),
+            ("done".into(), Value::Bool(node.done)),
+            ("priority".into(), Value::Number(node.priority as f64)),
+            ("weight".into(), Value::Number(node.weight as f64)),
+            (
+                "tags".into(),
+                Value::Array(node.tags.iter().cloned().map(Value::String).collect()),
+            ),
+            ("completed_at".into(), time_to_json(node.completed_at)),
+            (
+                "due".into(),
+                match node.due {
+                    Some(due) => Value::String(due.to_string()),
+                    None => Value::Null,
+                },
+            ),
+            ("created_at".into(), time_to_json(Some(node.created_at))),
+            ("modified_at".into(), time_to_json(Some(node.modified_at))),
+            ("children".into(), Value::Array(children)),
+        ])
+    }
+
+    /// Return `true` if `parent` may take one more child under `max_children`
+    fn has_room_for_child(&self, parent: &NodeRef<T>) -> bool {
+        self.max_children
+            .is_none_or(|max| parent.borrow().children.len() < max)
+    }
+
+    /// `has_room_for_child` as a `Result`, for the fallible mutators
+    fn check_room_for_child(&self, parent: &NodeRef<T>) -> Result<(), TreeError> {
+        match self.max_children {
+            Some(max) if !self.has_room_for_child(parent) => Err(TreeError::ChildLimit {
+                parent: parent.borrow().id,
+                max,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Look up a node, failing with `TreeError::NotFound`
+    fn node_or_err(&self, id: u32) -> Result<NodeRef<T>, TreeError> {
+        self.index.get(&id).cloned().ok_or(TreeError::NotFound(id))
+    }
+
+    /// Push a fresh edit onto the undo stack, invalidating the redo stack
+    fn record(&mut self, edit: Edit<T>) {
+        if let Some(event) = Self::edit_event(&edit, false) {
+            self.emit(event);
+        }
+
+        self.undo_stack.push_back(edit);
+        self.redo_stack.clear();
+        self.trim_history();
+    }
+
+    /// Call every listener with `event`
+    fn emit(&self, event: TreeEvent) {
+        for listener in self.listeners.borrow_mut().iter_mut() {
+            listener(event);
+        }
+    }
+
+    /// The structural event for an edit just applied, or just reverted when
+    /// `undone`. Done flags report themselves where they change.
+    fn edit_event(edit: &Edit<T>, undone: bool) -> Option<TreeEvent> {
+        match edit {
+            Edit::Added { node, slot } | Edit::Deleted { node, slot, .. } => {
+                let id = node.borrow().id;
+                let added = matches!(edit, Edit::Added { .. }) != undone;
+                Some(if added {
+                    TreeEvent::Added { id, parent: slot.0 }
+                } else {
+                    TreeEvent::Removed { id }
+                })
             }
-        } else {
-            // It's a root node
-            self.roots.retain(|root_ref| root_ref.borrow().id != id);
+            Edit::Moved { id, from, to } => Some(TreeEvent::Moved {
+                id: *id,
+                new_parent: if undone { from.0 } else { to.0 },
+            }),
+            // These report their own `Moved` events as they apply
+            Edit::Toggled { .. }
+            | Edit::SubtreeDone { .. }
+            | Edit::Reordered { .. }
+            | Edit::Swapped { .. } => None,
         }
+    }
 
-        // 2. Remove from index (this node + all descendants)
-        self.remove_from_index_rec(&node_ref);
+    fn trim_history(&mut self) {
+        while self.undo_stack.len() > self.history_limit {
+            self.undo_stack.pop_front();
+        }
+    }
+
+    /// Undo one edit without recording anything. Returns `false` if it no longer applies.
+    fn revert(&mut self, edit: &Edit<T>) -> bool {
+        match edit {
+            Edit::Added { node, .. } => self.unlink_subtree(node),
+            Edit::Deleted { node, slot, titles } => {
+                if !self.relink_subtree(node, *slot) {
+                    return false;
+                }
+                self.last_titles.extend(titles.iter().cloned());
+                true
+            }
+            Edit::Moved { id, from, .. } => self.move_to_slot(*id, *from),
+            Edit::Reordered { parent, before, .. } => self.reorder_to(*parent, before),
+            Edit::Swapped { a, b } => self.apply_swap(*a, *b),
+            Edit::Toggled { id, before, .. } | Edit::SubtreeDone { id, before, .. } => {
+                if !self.index.contains_key(id) {
+                    return false;
+                }
+                for (id, done) in before {
+                    if let Some(node_ref) = self.index.get(id)
+                        && node_ref.borrow_mut().set_done(*done)
+                    {
+                        self.emit(TreeEvent::DoneChanged {
+                            id: *id,
+                            done: *done,
+                        });
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    /// Redo one edit without recording anything. Returns `false` if it no longer applies.
+    fn reapply(&mut self, edit: &Edit<T>) -> bool {
+        match edit {
+            Edit::Added { node, slot } => self.relink_subtree(node, *slot),
+            Edit::Deleted { node, .. } => self.unlink_subtree(node),
+            Edit::Moved { id, to, .. } => self.move_to_slot(*id, *to),
+            Edit::Reordered { parent, after, .. } => self.reorder_to(*parent, after),
+            Edit::Swapped { a, b } => self.apply_swap(*a, *b),
+            Edit::Toggled { id, local, .. } => self.apply_toggle(*id, *local).is_some(),
+            Edit::SubtreeDone { id, done, .. } => match self.index.get(id).cloned() {
+                Some(node_ref) => {
+                    self.apply_subtree_done(&node_ref, *done);
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// Detach an indexed subtree and drop it from the index
+    fn unlink_subtree(&mut self, node_ref: &NodeRef<T>) -> bool {
+        let id = node_ref.borrow().id;
+        let indexed = self
+            .index
+            .get(&id)
+            .is_some_and(|current| Rc::ptr_eq(current, node_ref));
+        if !indexed || self.detach(node_ref).is_none() {
+            return false;
+        }
 
+        self.remove_from_index_rec(node_ref);
         true
     }
 
-    /// Move a node to a new parent. Returns `true` on success
-    ///
-    /// - Fails if `id == new_parent_id`
-    /// - Fails if `new_parent` is in the subtree of `id` (would create a cycle)
-    pub fn move_node(&mut self, id: u32, new_parent_id: u32) -> bool {
-        if id == new_parent_id {
+    /// Put a detached subtree back at `slot` and re-index it
+    fn relink_subtree(&mut self, node_ref: &NodeRef<T>, slot: Slot) -> bool {
+        if self.index.contains_key(&node_ref.borrow().id) {
             return false;
         }
 
+        let parent = match slot.0 {
+            Some(parent_id) => match self.index.get(&parent_id).cloned() {
+                Some(parent) => Some(parent),
+                None => return false,
+            },
+            None => None,
+        };
+
+        self.attach_at(node_ref, parent.as_ref(), slot.1);
+        self.index_subtree(node_ref);
+        true
+    }
+
+    /// Move an indexed node to `slot`, refusing moves that would create a cycle
+    fn move_to_slot(&mut self, id: u32, slot: Slot) -> bool {
         let Some(node_ref) = self.index.get(&id).cloned() else {
             return false;
         };
-        let Some(new_parent) = self.index.get(&new_parent_id).cloned() else {
-            return false;
+
+        let parent = match slot.0 {
+            Some(parent_id) => match self.index.get(&parent_id).cloned() {
+                Some(parent) if !Self::is_descendant(&node_ref, &parent) => Some(parent),
+                _ => return false,
+            },
+            None => None,
         };
 
-        if Self::is_descendant(&node_ref, &new_parent) {
+        if self.detach(&node_ref).is_none() {
             return false;
         }
+        self.attach_at(&node_ref, parent.as_ref(), slot.1);
+        true
+    }
 
-        // 1. Detach from old parent or roots
-        let old_parent_weak_opt = {
-            let node = node_ref.borrow();
-            node.parent.clone()
+    /// Flip a node's done flag and propagate, without recording history
+    fn apply_toggle(&mut self, id: u32, local: bool) -> Option<bool> {
+        let node = self.index.get(&id).cloned()?;
+        let done = {
+            // If the given id exists, toggle its `done` flag
+            let mut n = node.borrow_mut();
+            let done = !n.done;
+            n.set_done(done);
+            done
         };
+        self.emit(TreeEvent::Toggled { id, done });
 
-        if let Some(old_parent_weak) = old_parent_weak_opt {
-            if let Some(old_parent_rc) = old_parent_weak.upgrade() {
-                {
-                    let mut old_parent = old_parent_rc.borrow_mut();
-                    old_parent
+        if !local {
+            self.propagate_done_upward(&node);
+        }
+
+        let done = node.borrow().done;
+        Some(done)
+    }
+
+    /// Set `done` across a subtree and propagate, without recording history
+    fn apply_subtree_done(&mut self, node_ref: &NodeRef<T>, done: bool) {
+        for node_ref in Self::subtree_preorder(node_ref) {
+            let changed = node_ref.borrow_mut().set_done(done);
+            if changed {
+                let id = node_ref.borrow().id;
+                self.emit(TreeEvent::DoneChanged { id, done });
+            }
+        }
+
+        self.propagate_done_upward(node_ref);
+    }
+
+    /// Detach a node from its parent's children (or from `roots`), leaving
+    /// the index alone, and recompute done flags from the old parent upwards.
+    /// Returns the slot it occupied, or `None` if it wasn't linked in.
+    fn detach(&mut self, node_ref: &NodeRef<T>) -> Option<Slot> {
+        let parent_weak_opt = node_ref.borrow().parent.clone();
+
+        match parent_weak_opt {
+            Some(parent_weak) => {
+                let parent_rc = parent_weak.upgrade()?;
+                let position = {
+                    let mut parent = parent_rc.borrow_mut();
+                    let position = parent
                         .children
-                        .retain(|child_ref| child_ref.borrow().id != id);
-                }
+                        .iter()
+                        .position(|child_ref| Rc::ptr_eq(child_ref, node_ref))?;
+                    parent.children.remove(position);
+                    position
+                };
 
-                // Recompute done flags upward from old parent
-                Self::propagate_done_upward(&old_parent_rc);
+                // Recompute done flags upward from parent
+                self.propagate_done_upward(&parent_rc);
+
+                let parent_id = parent_rc.borrow().id;
+                Some((Some(parent_id), position))
+            }
+            None => {
+                // It's a root node
+                let position = self
+                    .roots
+                    .iter()
+                    .position(|root_ref| Rc::ptr_eq(root_ref, node_ref))?;
+                self.roots.remove(position);
+                Some((None, position))
             }
-        } else {
-            // It was a root node
-            self.roots.retain(|root_ref| root_ref.borrow().id != id);
         }
+    }
 
-        // 2. Attach to new parent
-        {
-            let mut node_mut = node_ref.borrow_mut();
-            node_mut.parent = Some(Rc::downgrade(&new_parent));
+    /// Insert a detached node under `parent` (or among the roots) at
+    /// `position`, clamped to the list length, and recompute done flags
+    /// upwards. Returns the position actually used. The index is left alone.
+    fn attach_at(
+        &mut self,
+        node_ref: &NodeRef<T>,
+        parent: Option<&NodeRef<T>>,
+        position: usize,
+    ) -> usize {
+        node_ref.borrow_mut().parent = parent.map(Rc::downgrade);
+
+        match parent {
+            Some(parent_rc) => {
+                let position = {
+                    let mut parent = parent_rc.borrow_mut();
+                    let position = position.min(parent.children.len());
+                    parent.children.insert(position, Rc::clone(node_ref));
+                    position
+                };
+
+                self.propagate_done_upward(parent_rc);
+                position
+            }
+            None => {
+                let position = position.min(self.roots.len());
+                self.roots.insert(position, Rc::clone(node_ref));
+                position
+            }
         }
-        {
-            let mut new_parent_mut = new_parent.borrow_mut();
-            new_parent_mut.children.push(node_ref.clone());
+    }
+
+    /// Add a node and all its descendants to the index
+    fn index_subtree(&mut self, node_ref: &NodeRef<T>) {
+        let mut stack = vec![Rc::clone(node_ref)];
+        while let Some(current) = stack.pop() {
+            let node = current.borrow();
+            stack.extend(node.children.iter().cloned());
+            self.index.insert(node.id, Rc::clone(&current));
         }
+    }
 
-        // 3. Recompute completion upwards from new parent
-        Self::propagate_done_upward(&new_parent);
+    /// Id of the first root titled `ARCHIVE_TITLE`, if any
+    fn archive_root_id(&self) -> Option<u32> {
+        self.roots
+            .iter()
+            .find(|root_ref| root_ref.borrow().title == ARCHIVE_TITLE)
+            .map(|root_ref| root_ref.borrow().id)
+    }
+
+    /// Return `true` if every node in the subtree of `root` is done
+    fn subtree_all_done(root: &NodeRef<T>) -> bool {
+        let mut stack = vec![Rc::clone(root)];
+        while let Some(node_ref) = stack.pop() {
+            let node = node_ref.borrow();
+            if !node.done {
+                return false;
+            }
+            stack.extend(node.children.iter().cloned());
+        }
 
         true
     }
 
-    /// Get a read-only handle to a node.
-    pub fn get(&self, id: u32) -> Option<NodeRef> {
-        self.index.get(&id).cloned()
+    /// Return `true` if `target` is in the subtree of `root`
+    fn is_descendant(root: &NodeRef<T>, target: &NodeRef<T>) -> bool {
+        // Walk up from `target`; bounded by its depth, not the subtree size
+        let mut current = Some(Rc::clone(target));
+        while let Some(node_ref) = current {
+            if Rc::ptr_eq(&node_ref, root) {
+                return true;
+            }
+            current = node_ref
+                .borrow()
+                .parent
+                .as_ref()
+                .and_then(|parent| parent.upgrade());
+        }
+
+        false
     }
 
-    /// Print the tree to the given formatter
-    pub fn fmt_pretty(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for root in &self.roots {
-            Self::fmt_node(root, 0, f)?;
+    /// Recompute a single node's `done` from its children per the active
+    /// policy. Leaves (and every node under `Manual`) are left as they are.
+    fn rollup_done(&self, node_ref: &NodeRef<T>) {
+        let (id, done) = {
+            let mut node = node_ref.borrow_mut();
+            if node.children.is_empty() {
+                return;
+            }
+
+            let mut children_done = node
+                .children
+                .iter()
+                .map(|child_ref| child_ref.borrow().done);
+            let done = match self.done_policy {
+                DonePolicy::AllChildren => children_done.all(|done| done),
+                DonePolicy::AnyChild => children_done.any(|done| done),
+                DonePolicy::Manual => node.done,
+            };
+            if !node.set_done(done) {
+                return;
+            }
+            (node.id, done)
+        };
+
+        // Borrow released, so listeners may look at the node
+        self.emit(TreeEvent::DoneChanged { id, done });
+    }
+
+    /// Recalculate this node's completion based on its children (per the
+    /// active `DonePolicy`), then propagage upwards via parent links.
+    ///
+    /// Does nothing while a batch is open; `end_batch` catches up instead.
+    fn propagate_done_upward(&self, node_ref: &NodeRef<T>) {
+        if self.in_batch || self.done_policy == DonePolicy::Manual {
+            return;
         }
 
-        Ok(())
+        let mut current = Some(Rc::clone(node_ref));
+        while let Some(node_ref) = current {
+            // Recompuate done for this node, based on its children
+            self.rollup_done(&node_ref);
+
+            // Now move to the parent, if it still exists
+            current = node_ref
+                .borrow()
+                .parent
+                .as_ref()
+                .and_then(|parent| parent.upgrade());
+        }
     }
+}
 
-    fn fmt_node(node_ref: &NodeRef, indent: usize, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let node = node_ref.borrow();
+/// Replace `path` with `contents` via a temporary sibling file and a rename,
+/// after copying any existing file to `<path>.bak`.
+///
+/// The temporary file gets a name no other writer holds (process id plus a
+/// counter, created with `create_new`), so concurrent saves of the same path
+/// can't write into each other's temporary file.
+fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    static TMP_COUNTER: AtomicU32 = AtomicU32::new(0);
 
-        // indentation
-        for _ in 0..indent {
-            write!(f, "  ")?;
+    let Some(file_name) = path.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("'{}' is not a file path", path.display()),
+        ));
+    };
+    // Same directory, so the rename never crosses filesystems
+    let sibling = |prefix: &str, suffix: &str| {
+        let mut name = OsString::from(prefix);
+        name.push(file_name);
+        name.push(suffix);
+        path.with_file_name(name)
+    };
+    let (tmp_path, mut file) = loop {
+        let count = TMP_COUNTER.fetch_add(1, AtomicOrdering::Relaxed);
+        let tmp_path = sibling(".", &format!(".{}.{}.tmp", std::process::id(), count));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+        {
+            Ok(file) => break (tmp_path, file),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
         }
+    };
 
-        writeln!(
-            f,
-            "[{}] {} (id: {})",
-            if node.done { "x" } else { " " },
-            node.title,
-            node.id
-        )?;
+    let mut replace = || {
+        file.write_all(contents.as_bytes())?;
+        // Make sure the data is on disk before it replaces anything
+        file.sync_all()?;
 
-        for child in &node.children {
-            Self::fmt_node(child, indent + 1, f)?;
+        if path.exists() {
+            fs::copy(path, sibling("", ".bak"))?;
         }
+        fs::rename(&tmp_path, path)
+    };
+    let result = replace();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
 
-        Ok(())
+    result
+}
+
+/// A timestamp as whole seconds since the epoch, or `null`
+fn time_to_json(time: Option<SystemTime>) -> Value {
+    match time.and_then(|time| time.duration_since(UNIX_EPOCH).ok()) {
+        Some(elapsed) => Value::Number(elapsed.as_secs() as f64),
+        None => Value::Null,
     }
+}
 
-    /// Allocate a new unique ID
-    fn alloc_id(&mut self) -> u32 {
-        let id = self.next_id;
-        self.next_id += 1;
+/// Read an optional epoch-seconds `field` of node `id`
+fn time_from_json(value: &Value, id: u32, field: &str) -> Result<Option<SystemTime>, String> {
+    match value.get(field) {
+        None | Some(Value::Null) => Ok(None),
+        Some(secs) => {
+            let secs = secs.as_u64().ok_or_else(|| {
+                format!("node {}: \"{}\" must be seconds since the epoch", id, field)
+            })?;
+            Ok(Some(UNIX_EPOCH + Duration::from_secs(secs)))
+        }
+    }
+}
 
-        id
+/// Checkbox mark for display: `x` done, `~` partly done, blank otherwise
+fn checkbox(done: bool, partial: bool) -> &'static str {
+    if done {
+        "x"
+    } else if partial {
+        "~"
+    } else {
+        " "
     }
+}
 
-    /// Remove this node and all descendants from the `index` map
-    fn remove_from_index_rec(&mut self, node_ref: &NodeRef) {
-        let node = node_ref.borrow();
-        self.index.remove(&node.id);
-        for child in &node.children {
-            // Recurse down
-            self.remove_from_index_rec(child);
+/// Escape text for use inside a double-quoted DOT string
+fn dot_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
         }
     }
 
-    /// Return `true` if `target` is in the subtree of `root`
-    fn is_descendant(root: &NodeRef, target: &NodeRef) -> bool {
-        if Rc::ptr_eq(root, target) {
-            return true;
+    out
+}
+
+/// Make text safe inside a quoted Mermaid label. Mermaid has no backslash
+/// escapes, only `#name;` / `#code;` entities, so `#` itself is encoded too.
+fn mermaid_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '#' => out.push_str("#35;"),
+            '"' => out.push_str("#quot;"),
+            '<' => out.push_str("#lt;"),
+            '>' => out.push_str("#gt;"),
+            '&' => out.push_str("#amp;"),
+            '\n' | '\r' => out.push(' '),
+            c => out.push(c),
         }
+    }
 
-        let node = root.borrow();
-        for child in &node.children {
-            if Self::is_descendant(child, target) {
-                // Recurse down
-                return true;
+    out
+}
+
+/// Quote a CSV field if it needs it, doubling any quotes inside
+fn csv_escape(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Split CSV text into records of fields, each with the line it starts on.
+/// Quoted fields may contain commas, `""` escapes and line breaks; blank
+/// lines are skipped.
+fn parse_csv(input: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut records = Vec::new();
+
+    let mut chars = input.chars().peekable();
+    let mut line_no = 1;
+    while chars.peek().is_some() {
+        let start_line = line_no;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        loop {
+            match chars.next() {
+                Some('"') if field.is_empty() => {
+                    // Quoted field, up to the closing quote
+                    loop {
+                        match chars.next() {
+                            Some('"') if chars.peek() == Some(&'"') => {
+                                chars.next();
+                                field.push('"');
+                            }
+                            Some('"') => break,
+                            Some(c) => {
+                                if c == '\n' {
+                                    line_no += 1;
+                                }
+                                field.push(c);
+                            }
+                            None => {
+                                return Err(format!(
+                                    "line {}: unterminated quoted field",
+                                    start_line
+                                ));
+                            }
+                        }
+                    }
+                    if !matches!(chars.peek(), None | Some(',' | '\r' | '\n')) {
+                        return Err(format!("line {}: text after a closing quote", line_no));
+                    }
+                }
+                Some(',') => fields.push(std::mem::take(&mut field)),
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') | None => {
+                    line_no += 1;
+                    break;
+                }
+                Some(c) => field.push(c),
             }
         }
+        fields.push(field);
 
-        false
+        if fields.len() > 1 || !fields[0].is_empty() {
+            records.push((start_line, fields));
+        }
     }
 
-    /// Recalculate this node's completion based on its children,
-    /// then propagage upwards via parent links.
-    fn propagate_done_upward(node_ref: &NodeRef) {
-        // Recompuate done for this node, based on its children
-        {
-            let mut node = node_ref.borrow_mut();
-            if !node.children.is_empty() {
-                let all_children_done = node
-                    .children
-                    .iter()
-                    .all(|child_ref| child_ref.borrow().done);
-                node.done = all_children_done;
-            }
+    Ok(records)
+}
+
+/// Escape text for use in XML content or attribute values
+fn xml_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
         }
+    }
 
-        // Now move to the parent
-        let parent_weak_opt = {
-            let node = node_ref.borrow();
-            node.parent.clone()
+    out
+}
+
+/// Drop a trailing ` (id: N)` annotation from an exported line, if present
+fn strip_id_suffix(text: &str) -> &str {
+    if let Some(start) = text.rfind(" (id: ")
+        && let Some(digits) = text[start + 6..].strip_suffix(')')
+        && !digits.is_empty()
+        && digits.chars().all(|c| c.is_ascii_digit())
+    {
+        return &text[..start];
+    }
+
+    text
+}
+
+/// Pre-order iterator over a tree's nodes (see `Tree::iter_dfs`).
+///
+/// Holds its own references, so the tree can be borrowed freely while iterating.
+pub struct DfsIter<T = ()> {
+    stack: Vec<NodeRef<T>>,
+}
+
+impl<T> Iterator for DfsIter<T> {
+    type Item = NodeRef<T>;
+
+    fn next(&mut self) -> Option<NodeRef<T>> {
+        let node_ref = self.stack.pop()?;
+        self.stack
+            .extend(node_ref.borrow().children.iter().rev().cloned());
+
+        Some(node_ref)
+    }
+}
+
+/// Level-order iterator over a tree's nodes (see `Tree::iter_bfs`).
+pub struct BfsIter<T = ()> {
+    queue: VecDeque<NodeRef<T>>,
+}
+
+impl<T> Iterator for BfsIter<T> {
+    type Item = NodeRef<T>;
+
+    fn next(&mut self) -> Option<NodeRef<T>> {
+        let node_ref = self.queue.pop_front()?;
+        self.queue
+            .extend(node_ref.borrow().children.iter().cloned());
+
+        Some(node_ref)
+    }
+}
+
+/// Lets the `fmt::Write`-based renderers stream into an `io::Write`,
+/// remembering the underlying I/O error (which `fmt::Error` can't carry).
+struct IoAdapter<'a, W: io::Write> {
+    inner: &'a mut W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> fmt::Write for IoAdapter<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+/// Deep copy: every node is a new `Rc<RefCell<Node>>` with the same id and
+/// fields, linked to its copied parent. Settings are copied too; the undo
+/// and redo history is not, since it refers to the original's nodes.
+impl<T: Clone> Clone for Tree<T> {
+    fn clone(&self) -> Self {
+        let mut tree = Tree {
+            next_id: self.next_id,
+            highlighted: self.highlighted,
+            done_policy: self.done_policy,
+            in_batch: self.in_batch,
+            last_titles: self.last_titles.clone(),
+            max_children: self.max_children,
+            history_limit: self.history_limit,
+            ..Tree::default()
         };
 
-        if let Some(parent_weak) = parent_weak_opt {
-            if let Some(parent_rc) = parent_weak.upgrade() {
-                // Proceed to parent only if it still exists
-                Self::propagate_done_upward(&parent_rc);
-            }
+        for root in &self.roots {
+            let copy = Self::copy_subtree(root, |id| id);
+            tree.roots.push(Rc::clone(&copy));
+            tree.index_subtree(&copy);
         }
+
+        tree
     }
 }
 
 /// For `println!("{}", tree);`
-impl fmt::Display for Tree {
+impl<T> fmt::Display for Tree<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.fmt_pretty(f)
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,1141 @@
+// src/tree/tests.rs
+
+use super::*;
+
+#[test]
+fn ensure_path_second_run_creates_nothing() {
+    let mut tree = Tree::new();
+
+    let (id, created) = tree.ensure_path("Work", &["Backlog", "Bugs"]).unwrap();
+    assert_eq!(created.len(), 3);
+    assert_eq!(created.last(), Some(&id));
+
+    let (again, created) = tree.ensure_path("Work", &["Backlog", "Bugs"]).unwrap();
+    assert_eq!(again, id);
+    assert!(created.is_empty());
+    assert_eq!(tree.node_count(), 3);
+}
+
+#[test]
+fn ensure_path_rejects_blank_titles_before_creating_anything() {
+    let mut tree = Tree::new();
+
+    assert_eq!(tree.ensure_path("  ", &["A"]), Err(TreeError::EmptyTitle));
+    assert_eq!(
+        tree.ensure_path("Work", &["A", ""]),
+        Err(TreeError::EmptyTitle)
+    );
+    assert_eq!(tree.node_count(), 0);
+}
+
+#[test]
+fn ensure_path_reports_child_limit() {
+    let mut tree: Tree = Tree::with_max_children(1);
+    tree.ensure_path("Work", &["A"]).unwrap();
+
+    let err = tree.ensure_path("Work", &["B", "C"]).unwrap_err();
+    assert!(matches!(err, TreeError::ChildLimit { max: 1, .. }));
+}
+
+#[test]
+fn search_regex_matches_titles_in_tree_order() {
+    let mut tree = Tree::new();
+    let work = tree.add_root("TODO work");
+    let ticket = tree.add_child(work, "fix ABC-123").unwrap();
+    let later = tree.add_root("todo later");
+
+    assert_eq!(tree.search_regex("^TODO").unwrap(), vec![work]);
+    assert_eq!(tree.search_regex("(?i)^todo").unwrap(), vec![work, later]);
+    assert_eq!(tree.search_regex(r"[A-Z]+-\d+").unwrap(), vec![ticket]);
+    assert!(tree.search_regex("nothing$").unwrap().is_empty());
+}
+
+#[test]
+fn search_regex_rejects_invalid_patterns() {
+    let tree: Tree = Tree::new();
+    assert!(tree.search_regex("(unclosed").is_err());
+}
+
+/// A single chain `0 > 1 > ... > depth - 1`, returning the root id
+fn deep_chain(depth: usize) -> (Tree, u32) {
+    let mut tree = Tree::new();
+    // Manual skips the walk back up to the root after every add
+    tree.set_done_policy(DonePolicy::Manual);
+
+    let root = tree.add_root("0");
+    let mut parent = root;
+    for i in 1..depth {
+        parent = tree.add_child(parent, i.to_string()).unwrap();
+    }
+
+    (tree, root)
+}
+
+#[test]
+fn delete_deep_chain_does_not_overflow() {
+    let (mut tree, root) = deep_chain(50_000);
+    assert_eq!(tree.node_count(), 50_000);
+
+    tree.delete(root).unwrap();
+    assert_eq!(tree.node_count(), 0);
+}
+
+#[test]
+fn json_round_trip_deep_chain_does_not_overflow() {
+    let (tree, _) = deep_chain(50_000);
+
+    // Compact only: pretty output indents each level, so it grows with the
+    // square of the depth
+    let json = tree.to_json_string(false);
+    let loaded: Tree = Tree::from_json_str(&json).unwrap();
+    assert_eq!(loaded.node_count(), 50_000);
+    assert!(loaded.structurally_eq(&tree));
+
+    let roots = tree.to_serializable();
+    let rebuilt: Tree = Tree::from_serializable(roots).unwrap();
+    assert_eq!(rebuilt.node_count(), 50_000);
+}
+
+#[test]
+fn sibling_navigation_at_first_middle_and_last() {
+    let mut tree: Tree = Tree::new();
+    let root = tree.add_root("root");
+    let first = tree.add_child(root, "first").unwrap();
+    let middle = tree.add_child(root, "middle").unwrap();
+    let last = tree.add_child(root, "last").unwrap();
+
+    assert_eq!(tree.prev_sibling(first), None);
+    assert_eq!(tree.next_sibling(first), Some(middle));
+    assert_eq!(tree.prev_sibling(middle), Some(first));
+    assert_eq!(tree.next_sibling(middle), Some(last));
+    assert_eq!(tree.prev_sibling(last), Some(middle));
+    assert_eq!(tree.next_sibling(last), None);
+
+    assert_eq!(tree.siblings(middle), vec![first, last]);
+    assert_eq!(tree.next_sibling(root), None);
+    assert_eq!(tree.prev_sibling(999), None);
+}
+
+/// A root with children "c", "a", "b" (created in that order)
+fn three_children() -> (Tree, u32, [u32; 3]) {
+    let mut tree = Tree::new();
+    let root = tree.add_root("root");
+    let c = tree.add_child(root, "c").unwrap();
+    let a = tree.add_child(root, "a").unwrap();
+    let b = tree.add_child(root, "b").unwrap();
+    (tree, root, [c, a, b])
+}
+
+#[test]
+fn undo_and_redo_cover_reorders() {
+    let (mut tree, root, [c, a, b]) = three_children();
+    let original = vec![c, a, b];
+
+    assert!(tree.sort_children(root, true));
+    assert_eq!(tree.child_order(Some(root)), vec![a, b, c]);
+    assert!(tree.undo());
+    assert_eq!(tree.child_order(Some(root)), original);
+    assert!(tree.redo());
+    assert_eq!(tree.child_order(Some(root)), vec![a, b, c]);
+    assert!(tree.undo());
+
+    assert!(tree.reverse_children(Some(root)));
+    assert!(tree.undo());
+    assert_eq!(tree.child_order(Some(root)), original);
+
+    assert!(tree.move_down(c));
+    assert_eq!(tree.child_order(Some(root)), vec![a, c, b]);
+    assert!(tree.undo());
+    assert_eq!(tree.child_order(Some(root)), original);
+
+    let other = tree.add_root("other");
+    tree.sort_roots(true);
+    assert_eq!(tree.child_order(None), vec![other, root]);
+    assert!(tree.undo());
+    assert_eq!(tree.child_order(None), vec![root, other]);
+}
+
+#[test]
+fn undo_reverts_swap() {
+    let (mut tree, root, [c, a, b]) = three_children();
+    let other = tree.add_root("other");
+    let leaf = tree.add_child(other, "leaf").unwrap();
+
+    assert!(tree.swap(a, leaf));
+    assert_eq!(tree.child_order(Some(root)), vec![c, leaf, b]);
+    assert!(tree.undo());
+    assert_eq!(tree.child_order(Some(root)), vec![c, a, b]);
+    assert_eq!(tree.child_order(Some(other)), vec![leaf]);
+    assert!(tree.redo());
+    assert_eq!(tree.child_order(Some(other)), vec![a]);
+}
+
+#[test]
+fn undo_discards_an_edit_that_no_longer_applies() {
+    let mut tree = Tree::new();
+    let kept = tree.add_root("kept");
+    let lost = tree.add_root("lost");
+    // Drop `lost` from the index behind the history's back
+    tree.set_index_entry(lost, None);
+
+    assert!(tree.can_undo());
+    assert!(!tree.undo());
+    // The stale edit is gone; the one before it still undoes
+    assert!(tree.undo());
+    assert!(tree.get(kept).is_none());
+    assert!(!tree.can_undo());
+    assert!(!tree.undo());
+}
+
+#[test]
+fn sort_that_moves_nothing_is_not_recorded() {
+    let (mut tree, root, _) = three_children();
+    tree.sort_children(root, false);
+    // Only the root and the three children remain to undo
+    for _ in 0..4 {
+        assert!(tree.undo());
+    }
+    assert!(!tree.undo());
+}
+
+#[test]
+fn undo_delete_restores_rename_stash() {
+    let (mut tree, root, [c, ..]) = three_children();
+    tree.rename(c, "renamed").unwrap();
+
+    tree.delete(root).unwrap();
+    assert!(tree.undo());
+    assert_eq!(tree.rename_undo(c).as_deref(), Some("c"));
+    assert_eq!(tree.get(c).unwrap().borrow().title, "c");
+}
+
+#[test]
+fn archive_completed_branches_leaves_done_roots_whole() {
+    let mut tree = Tree::new();
+    let work = tree.add_root("work");
+    let shipped = tree.add_child(work, "shipped").unwrap();
+    let shipped_leaf = tree.add_child(shipped, "release notes").unwrap();
+    let open = tree.add_child(work, "open").unwrap();
+    tree.toggle(shipped_leaf);
+
+    let home = tree.add_root("home");
+    let chore = tree.add_child(home, "chore").unwrap();
+    tree.toggle(chore);
+
+    assert_eq!(tree.archive_completed_branches(), (vec![shipped], vec![]));
+    let archive = tree.archive_root_id().unwrap();
+    assert_eq!(tree.child_order(Some(archive)), vec![shipped]);
+    assert_eq!(tree.child_order(Some(work)), vec![open]);
+    // The done root keeps its children until the caller archives it
+    assert_eq!(tree.child_order(Some(home)), vec![chore]);
+    assert_eq!(tree.completed_roots(), vec![home]);
+}
+
+#[test]
+fn archive_completed_branches_reports_a_full_archive() {
+    let mut tree: Tree = Tree::with_max_children(2);
+    let archive = tree.add_root(ARCHIVE_TITLE);
+    tree.add_child(archive, "old").unwrap();
+    tree.add_child(archive, "older").unwrap();
+    let work = tree.add_root("work");
+    let shipped = tree.add_child(work, "shipped").unwrap();
+    let open = tree.add_child(work, "open").unwrap();
+    tree.toggle(shipped);
+
+    assert_eq!(tree.archive_completed_branches(), (vec![], vec![shipped]));
+    assert_eq!(tree.child_order(Some(work)), vec![shipped, open]);
+}
+
+#[test]
+fn prune_done_removes_maximal_done_branches() {
+    let mut tree = Tree::new();
+    let work = tree.add_root("work");
+    let shipped = tree.add_child(work, "shipped").unwrap();
+    let shipped_leaf = tree.add_child(shipped, "release notes").unwrap();
+    let open = tree.add_child(work, "open").unwrap();
+    tree.toggle(shipped_leaf);
+    let home = tree.add_root("home");
+    let chore = tree.add_child(home, "chore").unwrap();
+    tree.toggle(chore);
+
+    assert_eq!(tree.prune_done(), 4);
+    assert_eq!(tree.child_order(None), vec![work]);
+    assert_eq!(tree.child_order(Some(work)), vec![open]);
+}
+
+/// The error of a load that should have failed
+fn load_err(result: Result<Tree, String>) -> String {
+    match result {
+        Ok(_) => panic!("expected the load to fail"),
+        Err(err) => err,
+    }
+}
+
+/// "plan" with children "design" (under it "sketch", done) and "build"
+fn small_plan() -> Tree {
+    let mut tree = Tree::new();
+    let plan = tree.add_root("plan");
+    let design = tree.add_child(plan, "design").unwrap();
+    let sketch = tree.add_child(design, "sketch").unwrap();
+    tree.add_child(plan, "build").unwrap();
+    tree.toggle(sketch);
+    tree
+}
+
+#[test]
+fn from_jsonl_rejects_cycles_and_missing_parents() {
+    let cycle = concat!(
+        "{\"id\":1,\"parent\":null,\"done\":false,\"title\":\"root\"}\n",
+        "{\"id\":2,\"parent\":3,\"done\":false,\"title\":\"a\"}\n",
+        "{\"id\":3,\"parent\":2,\"done\":false,\"title\":\"b\"}\n",
+    );
+    let err = load_err(Tree::<()>::from_jsonl(cycle.as_bytes()));
+    assert!(err.contains("cycle"), "{}", err);
+
+    let own_parent = "{\"id\":1,\"parent\":1,\"done\":false,\"title\":\"self\"}\n";
+    assert!(Tree::<()>::from_jsonl(own_parent.as_bytes()).is_err());
+
+    let orphan = "{\"id\":1,\"parent\":7,\"done\":false,\"title\":\"orphan\"}\n";
+    let err = load_err(Tree::<()>::from_jsonl(orphan.as_bytes()));
+    assert_eq!(err, "line 1: parent 7 of node 1 not found");
+}
+
+#[test]
+fn csv_round_trip_and_cycle() {
+    let tree = small_plan();
+    let loaded: Tree = Tree::from_csv(&tree.to_csv()).unwrap();
+    assert!(loaded.structurally_eq(&tree));
+
+    let cycle = "id,parent_id,title,done\n1,2,a,false\n2,1,b,false\n";
+    let err = load_err(Tree::<()>::from_csv(cycle));
+    assert!(err.contains("cycle"), "{}", err);
+}
+
+#[test]
+fn title_case_modes() {
+    let cases = [
+        (TitleCaseMode::Sentence, "Fix the login page"),
+        (TitleCaseMode::Title, "Fix The Login Page"),
+        (TitleCaseMode::Upper, "FIX THE LOGIN PAGE"),
+        (TitleCaseMode::Lower, "fix the login page"),
+    ];
+    for (mode, expected) in cases {
+        assert_eq!(mode.apply("fix the LOGIN page"), expected, "{:?}", mode);
+    }
+}
+
+#[test]
+fn apply_title_case_touches_only_changed_nodes() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("Inbox");
+    let shout = tree.add_child(root, "CALL BACK").unwrap();
+    let calm = tree.add_child(root, "Plan").unwrap();
+    for node_ref in tree.iter_dfs() {
+        node_ref.borrow_mut().modified_at = SystemTime::UNIX_EPOCH;
+    }
+
+    assert_eq!(tree.apply_title_case(TitleCaseMode::Sentence), 1);
+    let modified = |id| tree.get(id).unwrap().borrow().modified_at;
+    assert_eq!(tree.get(shout).unwrap().borrow().title, "Call back");
+    assert!(modified(shout) > SystemTime::UNIX_EPOCH);
+    assert_eq!(modified(root), SystemTime::UNIX_EPOCH);
+    assert_eq!(modified(calm), SystemTime::UNIX_EPOCH);
+}
+
+#[test]
+fn summary_agrees_with_stats() {
+    let mut tree = Tree::new();
+    assert_eq!(tree.summary(), "empty tree");
+
+    let root = tree.add_root("root");
+    assert_eq!(tree.stats().max_depth, 0);
+    assert_eq!(tree.summary(), "1 root, 1 node, 0 done, max depth 0");
+
+    let child = tree.add_child(root, "child").unwrap();
+    let grandchild = tree.add_child(child, "grandchild").unwrap();
+    tree.add_root("other");
+    tree.toggle(grandchild);
+
+    let stats = tree.stats();
+    assert_eq!(stats.max_depth, tree.depth(grandchild).unwrap());
+    assert_eq!(
+        stats,
+        TreeStats {
+            total_nodes: 4,
+            done_nodes: 3,
+            leaf_count: 2,
+            root_count: 2,
+            max_depth: 2,
+        }
+    );
+    assert_eq!(tree.summary(), "2 roots, 4 nodes, 3 done, max depth 2");
+}
+
+#[test]
+fn save_json_keeps_a_backup_and_no_temp_files() {
+    let dir = std::env::temp_dir().join(format!("treedesk-save-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("tree.json");
+
+    let mut tree = Tree::new();
+    tree.add_root("first");
+    tree.save_json(&path).unwrap();
+    tree.add_root("second");
+    tree.save_json(&path).unwrap();
+
+    let saved: Tree = Tree::load_json(&path).unwrap();
+    assert!(saved.structurally_eq(&tree));
+    let backup: Tree = Tree::load_json(&dir.join("tree.json.bak")).unwrap();
+    assert_eq!(backup.node_count(), 1);
+
+    let mut names: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["tree.json", "tree.json.bak"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn children_view_sorts_without_mutating() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("root");
+    let pear = tree.add_child(root, "pear").unwrap();
+    let apple = tree.add_child(root, "Apple").unwrap();
+    let fig = tree.add_child(root, "fig").unwrap();
+    tree.toggle(apple);
+
+    let by_title = tree
+        .children_view(root, |a, b| {
+            a.title.to_lowercase().cmp(&b.title.to_lowercase())
+        })
+        .unwrap();
+    assert_eq!(by_title, vec![apple, fig, pear]);
+
+    let done_last = tree
+        .children_view(root, |a, b| a.done.cmp(&b.done))
+        .unwrap();
+    assert_eq!(done_last, vec![pear, fig, apple]);
+
+    assert_eq!(tree.child_order(Some(root)), vec![pear, apple, fig]);
+    assert_eq!(tree.children_view(999, |_, _| Ordering::Equal), None);
+}
+
+#[test]
+fn highlight_marks_the_root_path() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("root");
+    let branch = tree.add_child(root, "branch").unwrap();
+    let leaf = tree.add_child(branch, "leaf").unwrap();
+    tree.add_child(root, "other").unwrap();
+
+    assert!(tree.set_highlight(Some(leaf)));
+    let shown = tree.to_string();
+    let marked: Vec<&str> = shown
+        .lines()
+        .filter(|line| line.starts_with("» "))
+        .collect();
+    assert_eq!(marked.len(), 3);
+    for (line, title) in marked.iter().zip(["root", "branch", "leaf"]) {
+        assert!(line.contains(&format!("{} (id:", title)), "{}", line);
+    }
+    assert!(
+        shown
+            .lines()
+            .any(|line| line.starts_with("  ") && line.contains("other"))
+    );
+
+    assert!(tree.set_highlight(None));
+    assert!(!tree.to_string().contains('»'));
+    assert!(!tree.set_highlight(Some(999)));
+
+    // A highlighted node that goes away marks nothing
+    assert!(tree.set_highlight(Some(leaf)));
+    tree.delete(leaf).unwrap();
+    assert!(!tree.to_string().contains('»'));
+}
+
+#[test]
+fn edge_and_branch_counts() {
+    let mut tree = Tree::new();
+    assert_eq!((tree.edge_count(), tree.branch_count()), (0, 0));
+
+    let root = tree.add_root("root");
+    let a = tree.add_child(root, "a").unwrap();
+    tree.add_child(root, "b").unwrap();
+    tree.add_child(a, "a1").unwrap();
+    tree.add_child(a, "a2").unwrap();
+    tree.add_child(a, "a3").unwrap();
+    tree.add_root("lone");
+
+    // 7 nodes in 2 trees: 5 edges over 2 non-leaf nodes
+    assert_eq!(tree.edge_count(), tree.node_count() - tree.root_count());
+    assert_eq!(tree.edge_count(), 5);
+    assert_eq!(tree.branch_count(), 2);
+    assert_eq!(tree.edge_count() as f64 / tree.branch_count() as f64, 2.5);
+}
+
+#[test]
+fn subtree_contains_true_and_false() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("root");
+    let branch = tree.add_child(root, "branch").unwrap();
+    let leaf = tree.add_child(branch, "leaf").unwrap();
+    let other = tree.add_root("other");
+
+    assert_eq!(tree.subtree_contains(root, leaf), Some(true));
+    assert_eq!(tree.subtree_contains(branch, branch), Some(true));
+    assert_eq!(tree.subtree_contains(leaf, branch), Some(false));
+    assert_eq!(tree.subtree_contains(other, leaf), Some(false));
+    assert_eq!(tree.subtree_contains(root, 999), Some(false));
+    assert_eq!(tree.subtree_contains(999, leaf), None);
+}
+
+#[test]
+fn rebuild_index_recovers_a_corrupted_index() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("root");
+    let child = tree.add_child(root, "child").unwrap();
+    let stray = Node::new(42, "stray", None);
+
+    tree.set_index_entry(child, None);
+    tree.set_index_entry(42, Some(stray));
+    assert!(tree.get(child).is_none());
+    assert!(tree.get(42).is_some());
+    assert!(tree.validate().is_err());
+
+    assert_eq!(tree.rebuild_index(), 2);
+    assert_eq!(tree.get(child).unwrap().borrow().title, "child");
+    assert!(tree.get(42).is_none());
+    assert!(tree.validate().is_ok());
+    assert_eq!(tree.add_root("next"), child + 1);
+}
+
+#[test]
+fn toggle_get_alternates() {
+    let mut tree = Tree::new();
+    let id = tree.add_root("task");
+
+    assert_eq!(tree.toggle_get(id), Some(true));
+    assert_eq!(tree.toggle_get(id), Some(false));
+    assert_eq!(tree.toggle_get(id), Some(true));
+    assert_eq!(tree.toggle_get(999), None);
+}
+
+#[test]
+fn find_any_and_find_all() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("Fix login bug");
+    let write = tree.add_child(root, "write login test").unwrap();
+    let docs = tree.add_child(root, "update docs").unwrap();
+    tree.add_child(docs, "unrelated").unwrap();
+
+    assert_eq!(tree.find_any(&["LOGIN", "docs"]), vec![root, write, docs]);
+    assert_eq!(tree.find_all(&["login", "test"]), vec![write]);
+    assert_eq!(tree.find_all(&["login", "docs"]), Vec::<u32>::new());
+    // A node matching several terms is listed once
+    assert_eq!(tree.find_any(&["fix", "bug"]), vec![root]);
+}
+
+#[test]
+fn flat_records_list_parents_before_children() {
+    let mut tree = Tree::new();
+    let a = tree.add_root("a");
+    let b = tree.add_root("b");
+    let b1 = tree.add_child(b, "b1").unwrap();
+    tree.add_child(a, "a1").unwrap();
+    tree.add_child(b1, "b1x").unwrap();
+    // Move a node under a later-created parent, so ids alone would mislead
+    tree.move_node(a, b1).unwrap();
+
+    let records = tree.to_flat_records();
+    assert_eq!(records.len(), tree.node_count());
+    for (i, record) in records.iter().enumerate() {
+        match record.parent_id {
+            Some(parent_id) => {
+                let parent_pos = records.iter().position(|r| r.id == parent_id).unwrap();
+                assert!(parent_pos < i, "{} listed before its parent", record.id);
+                assert_eq!(record.depth, records[parent_pos].depth + 1);
+            }
+            None => assert_eq!(record.depth, 0),
+        }
+    }
+}
+
+#[test]
+fn compact_display_joins_chains_only() {
+    let mut tree = Tree::new();
+    let a = tree.add_root("A");
+    let b = tree.add_child(a, "B").unwrap();
+    let c = tree.add_child(b, "C").unwrap();
+    let d = tree.add_child(c, "D").unwrap();
+    let e = tree.add_child(c, "E").unwrap();
+    let f = tree.add_child(e, "F").unwrap();
+
+    assert_eq!(
+        tree.to_compact_string(),
+        format!(
+            "[ ] A > B > C (id: {})\n  [ ] D (id: {})\n  [ ] E > F (id: {})\n",
+            c, d, f
+        )
+    );
+}
+
+/// A parent with two children, under `policy`
+fn policy_tree(policy: DonePolicy) -> (Tree, u32, u32, u32) {
+    let mut tree = Tree::new();
+    tree.set_done_policy(policy);
+    let parent = tree.add_root("parent");
+    let first = tree.add_child(parent, "first").unwrap();
+    let second = tree.add_child(parent, "second").unwrap();
+    (tree, parent, first, second)
+}
+
+fn is_done(tree: &Tree, id: u32) -> bool {
+    tree.get(id).unwrap().borrow().done
+}
+
+#[test]
+fn all_children_policy_needs_every_child() {
+    let (mut tree, parent, first, second) = policy_tree(DonePolicy::AllChildren);
+    tree.toggle(first);
+    assert!(!is_done(&tree, parent));
+    tree.toggle(second);
+    assert!(is_done(&tree, parent));
+    // A new undone child reopens the parent
+    tree.add_child(parent, "third").unwrap();
+    assert!(!is_done(&tree, parent));
+}
+
+#[test]
+fn any_child_policy_needs_one_child() {
+    let (mut tree, parent, first, _) = policy_tree(DonePolicy::AnyChild);
+    tree.toggle(first);
+    assert!(is_done(&tree, parent));
+    tree.toggle(first);
+    assert!(!is_done(&tree, parent));
+}
+
+#[test]
+fn manual_policy_never_changes_parents() {
+    let (mut tree, parent, first, second) = policy_tree(DonePolicy::Manual);
+    tree.toggle(first);
+    tree.toggle(second);
+    assert!(!is_done(&tree, parent));
+
+    tree.toggle(parent);
+    tree.toggle(first);
+    assert!(is_done(&tree, parent));
+}
+
+#[test]
+fn pretty_json_reparses_to_an_equal_tree() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("root \"quoted\"");
+    let child = tree.add_child(root, "child").unwrap();
+    tree.add_child(root, "second\nline").unwrap();
+    tree.toggle(child);
+
+    let pretty = tree.to_json_string(true);
+    assert!(pretty.contains("\n  "));
+    assert_ne!(pretty, tree.to_json_string(false));
+
+    let loaded: Tree = Tree::from_json_str(&pretty).unwrap();
+    assert!(loaded.structurally_eq(&tree));
+    assert_eq!(loaded.to_json_string(false), tree.to_json_string(false));
+}
+
+#[test]
+fn tag_histogram_counts_every_tag_of_a_node() {
+    let mut tree = Tree::new();
+    let a = tree.add_root("a");
+    let b = tree.add_root("b");
+    let c = tree.add_root("c");
+    tree.add_tag(a, "work");
+    tree.add_tag(a, "urgent");
+    tree.add_tag(b, "work");
+    tree.add_tag(b, "home");
+    tree.add_tag(c, "work");
+    tree.add_tag(c, "urgent");
+
+    assert_eq!(
+        tree.tag_histogram(),
+        vec![
+            ("work".to_string(), 3),
+            ("urgent".to_string(), 2),
+            ("home".to_string(), 1),
+        ]
+    );
+}
+
+#[test]
+fn batched_edits_end_like_unbatched_ones() {
+    let build = |batched: bool| {
+        let mut tree = Tree::new();
+        if batched {
+            tree.begin_batch();
+        }
+        let root = tree.add_root("root");
+        let a = tree.add_child(root, "a").unwrap();
+        let b = tree.add_child(root, "b").unwrap();
+        let b1 = tree.add_child(b, "b1").unwrap();
+        let other = tree.add_root("other");
+        tree.toggle(a);
+        tree.toggle(b1);
+        tree.move_node(other, root).unwrap();
+        tree.toggle(other);
+        if batched {
+            assert!(tree.end_batch());
+        }
+        tree
+    };
+
+    let plain = build(false);
+    let batched = build(true);
+    assert!(batched.structurally_eq(&plain));
+    assert_eq!(batched.to_flat_records(), plain.to_flat_records());
+    assert!(plain.get(1).unwrap().borrow().done);
+}
+
+#[test]
+fn render_matches_display() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("root");
+    let child = tree.add_child(root, "child").unwrap();
+    tree.add_child(child, "grandchild").unwrap();
+    tree.add_child(root, "ünïcode").unwrap();
+    tree.set_priority(child, 2);
+    tree.toggle(child);
+
+    let mut bytes: Vec<u8> = Vec::new();
+    tree.render(&mut bytes).unwrap();
+    assert_eq!(String::from_utf8(bytes).unwrap(), tree.to_string());
+
+    let mut empty: Vec<u8> = Vec::new();
+    Tree::<()>::new().render(&mut empty).unwrap();
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn find_first_stops_at_the_first_match() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("root");
+    let target = tree.add_child(root, "target").unwrap();
+    tree.add_child(root, "target too").unwrap();
+    tree.add_root("later");
+
+    let visited = std::cell::Cell::new(0);
+    let found = tree.find_first(|node| {
+        visited.set(visited.get() + 1);
+        node.title.starts_with("target")
+    });
+    assert_eq!(found, Some(target));
+    assert_eq!(visited.get(), 2);
+
+    assert_eq!(tree.find_first(|node| node.title == "missing"), None);
+}
+
+#[test]
+fn markdown_round_trip() {
+    let tree = small_plan();
+    let md = tree.to_markdown();
+
+    let loaded: Tree = Tree::from_markdown(&md).unwrap();
+    assert!(loaded.structurally_eq(&tree));
+    assert_eq!(loaded.to_markdown(), md);
+}
+
+#[test]
+fn from_markdown_rejects_non_task_lines() {
+    let err = load_err(Tree::from_markdown("- [ ] ok\n  just text\n"));
+    assert!(err.starts_with("line 2"), "{}", err);
+}
+
+#[test]
+fn with_starting_id_allocates_from_start() {
+    let mut tree: Tree = Tree::with_starting_id(100);
+    let root = tree.add_root("root");
+    assert_eq!(root, 100);
+
+    let reserved = tree.reserve_id();
+    assert_eq!(reserved, 101);
+    let child = tree.add_child(root, "child").unwrap();
+    assert_eq!(child, 102);
+    assert!(tree.get(reserved).is_none());
+}
+
+#[test]
+fn breadcrumb_truncates_in_the_middle() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("Project");
+    let backend = tree.add_child(root, "Backend").unwrap();
+    let auth = tree.add_child(backend, "Authentication").unwrap();
+    let tokens = tree.add_child(auth, "Refresh tokens").unwrap();
+
+    assert_eq!(
+        tree.breadcrumb(tokens, 100).unwrap(),
+        "Project / Backend / Authentication / Refresh tokens"
+    );
+    assert_eq!(
+        tree.breadcrumb(tokens, 45).unwrap(),
+        "Project / … / Authentication / Refresh tokens"
+    );
+    assert_eq!(
+        tree.breadcrumb(tokens, 30).unwrap(),
+        "Project / … / Refresh tokens"
+    );
+    // The last segment is never cut
+    assert_eq!(tree.breadcrumb(tokens, 5).unwrap(), "… / Refresh tokens");
+    assert_eq!(tree.breadcrumb(999, 40), None);
+}
+
+#[test]
+fn fix_node_done_repairs_an_inconsistent_parent() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("root");
+    let parent = tree.add_child(root, "parent").unwrap();
+    let a = tree.add_child(parent, "a").unwrap();
+    let b = tree.add_child(parent, "b").unwrap();
+    // Mark the children done behind the tree's back
+    for id in [a, b] {
+        tree.get(id).unwrap().borrow_mut().done = true;
+    }
+    assert!(!is_done(&tree, parent));
+
+    assert_eq!(tree.fix_node_done(parent), Some(true));
+    assert!(is_done(&tree, parent));
+    // The fix propagates to the ancestors too
+    assert!(is_done(&tree, root));
+    assert_eq!(tree.fix_node_done(999), None);
+}
+
+#[test]
+fn tsv_sanitizes_tabs_and_newlines_in_titles() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("a\tb");
+    tree.add_child(root, "line one\nline two").unwrap();
+
+    assert_eq!(
+        tree.to_tsv(),
+        format!(
+            "id\tdepth\tdone\ttitle\n{}\t0\tfalse\ta b\n{}\t1\tfalse\tline one line two\n",
+            root,
+            root + 1
+        )
+    );
+}
+
+#[test]
+fn pending_leaves_by_priority_orders_mixed_priorities() {
+    let mut tree = Tree::new();
+    let root = tree.add_root("root");
+    let low = tree.add_child(root, "low").unwrap();
+    let high = tree.add_child(root, "high").unwrap();
+    let plain = tree.add_child(root, "plain").unwrap();
+    let also_high = tree.add_child(root, "also high").unwrap();
+    let finished = tree.add_child(root, "finished").unwrap();
+    tree.set_priority(low, 1);
+    tree.set_priority(high, 3);
+    tree.set_priority(also_high, 3);
+    tree.set_priority(finished, 5);
+    tree.toggle(finished);
+
+    // Done leaves and the parent are left out; equal priorities stay in tree order
+    assert_eq!(
+        tree.pending_leaves_by_priority(),
+        vec![high, also_high, low, plain]
+    );
+}
+
+#[test]
+fn post_order_visits_children_before_parents() {
+    let tree = small_plan();
+    let mut order = Vec::new();
+    tree.for_each_post_order(|node| order.push(node.title.clone()));
+    assert_eq!(order, ["sketch", "design", "build", "plan"]);
+
+    let mut ids = Vec::new();
+    tree.for_each_post_order(|node| ids.push(node.id));
+    for node_ref in tree.iter_dfs() {
+        let node = node_ref.borrow();
+        let pos = ids.iter().position(|&id| id == node.id).unwrap();
+        for child in &node.children {
+            let child_pos = ids.iter().position(|&id| id == child.borrow().id).unwrap();
+            assert!(child_pos < pos);
+        }
+    }
+}
+
+#[test]
+fn rename_then_rename_undo() {
+    let mut tree = Tree::new();
+    let id = tree.add_root("tpyo");
+
+    assert_eq!(tree.rename_undo(id), None);
+    tree.rename(id, "typo").unwrap();
+    tree.rename(id, "typo fixed").unwrap();
+    // One level per node: the second rename replaced the stash
+    assert_eq!(tree.rename_undo(id).as_deref(), Some("typo"));
+    assert_eq!(tree.get(id).unwrap().borrow().title, "typo");
+    assert_eq!(tree.rename_undo(id), None);
+
+    assert!(matches!(tree.rename(id, "  "), Err(TreeError::EmptyTitle)));
+}
+
+#[test]
+fn completed_flat_lists_qualified_done_leaves() {
+    let mut tree = Tree::new();
+    let project = tree.add_root("Project");
+    let backend = tree.add_child(project, "Backend").unwrap();
+    let auth = tree.add_child(backend, "Auth").unwrap();
+    let api = tree.add_child(backend, "API").unwrap();
+    let docs = tree.add_child(project, "Docs").unwrap();
+    tree.toggle(auth);
+    tree.toggle(docs);
+
+    assert_eq!(
+        tree.completed_flat(),
+        vec![
+            (auth, "Project / Backend / Auth".to_string()),
+            (docs, "Project / Docs".to_string()),
+        ]
+    );
+    // Parents done by propagation are not leaves
+    tree.toggle(api);
+    assert_eq!(tree.completed_flat().len(), 3);
+}
+
+#[test]
+fn capped_parent_refuses_one_more_child() {
+    let mut tree: Tree = Tree::with_max_children(2);
+    let root = tree.add_root("root");
+    tree.add_child(root, "a").unwrap();
+    tree.add_child(root, "b").unwrap();
+    let other = tree.add_root("other");
+    let loose = tree.add_child(other, "loose").unwrap();
+    let before = tree.to_flat_records();
+
+    let err = tree.add_child(root, "c").unwrap_err();
+    assert!(matches!(err, TreeError::ChildLimit { parent, max: 2 } if parent == root));
+    assert!(err.to_string().contains(&root.to_string()));
+    assert!(tree.move_node(loose, root).is_err());
+    assert!(tree.duplicate(loose, root).is_err());
+
+    assert_eq!(tree.to_flat_records(), before);
+}
+
+#[test]
+fn capped_tree_refuses_an_over_cap_merge() {
+    let mut tree: Tree = Tree::with_max_children(2);
+    let root = tree.add_root("root");
+    tree.add_child(root, "a").unwrap();
+    let before = tree.to_flat_records();
+    let next_id = tree.next_id;
+
+    let mut other = Tree::new();
+    other.add_root("fits");
+    let wide = other.add_root("wide");
+    for title in ["x", "y", "z"] {
+        other.add_child(wide, title).unwrap();
+    }
+
+    let err = tree.merge(other).unwrap_err();
+    assert_eq!(
+        err,
+        TreeError::ChildLimit {
+            parent: wide,
+            max: 2
+        }
+    );
+    assert_eq!(tree.to_flat_records(), before);
+    assert_eq!(tree.next_id, next_id);
+    assert!(tree.undo());
+    assert_eq!(tree.node_count(), 1);
+}
+
+#[test]
+fn subtree_outline_round_trip() {
+    let mut tree = small_plan();
+    let design = tree.find_first(|node| node.title == "design").unwrap();
+    let outline = tree.subtree_to_outline(design).unwrap();
+    assert_eq!(outline, "[x] design\n  [x] sketch\n");
+
+    // Re-import under another parent and compare
+    let piece: Tree = Tree::from_outline(&outline).unwrap();
+    let target = tree.add_root("target");
+    let copy = tree.merge(piece).unwrap()[0];
+    tree.move_node(copy, target).unwrap();
+    assert_eq!(tree.subtree_to_outline(copy).unwrap(), outline);
+    assert_eq!(tree.subtree_to_outline(999), None);
+}
+
+#[test]
+fn completions_per_day_buckets_by_date() {
+    let mut tree = Tree::new();
+    let day = |n: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(n * 86_400 + 3_600);
+    let stamps = [Some(day(2)), Some(day(0)), Some(day(2)), None];
+    for (i, stamp) in stamps.into_iter().enumerate() {
+        let id = tree.add_root(format!("task {}", i));
+        tree.get(id).unwrap().borrow_mut().completed_at = stamp;
+    }
+
+    assert_eq!(
+        tree.completions_per_day(),
+        vec![("1970-01-01".to_string(), 1), ("1970-01-03".to_string(), 2)]
+    );
+}
+
+#[test]
+fn weak_link_report_flags_a_stale_parent() {
+    let mut tree = Tree::new();
+    let a = tree.add_root("a");
+    let b = tree.add_root("b");
+    let child = tree.add_child(a, "child").unwrap();
+    assert!(tree.weak_link_report().is_empty());
+
+    // Point the child at `b` while `a` still lists it
+    let b_ref = tree.get(b).unwrap();
+    tree.get(child).unwrap().borrow_mut().parent = Some(Rc::downgrade(&b_ref));
+
+    let report = tree.weak_link_report();
+    assert!(
+        report.contains(&format!(
+            "node {}: parent {} does not list it as a child",
+            child, b
+        )),
+        "{:?}",
+        report
+    );
+    assert!(report.contains(&format!(
+        "node {}: listed under {} but its parent pointer points elsewhere",
+        child, a
+    )));
+}
+
+#[test]
+fn svg_has_one_text_per_node() {
+    let mut tree = small_plan();
+    tree.add_root("a < b & \"c\"");
+
+    let svg = tree.to_svg();
+    assert!(svg.starts_with("<svg"));
+    assert_eq!(svg.matches("<text").count(), tree.node_count());
+    assert!(svg.contains("a &lt; b &amp;"));
+}
+
+#[test]
+fn iter_bfs_yields_level_by_level() {
+    let mut tree = Tree::new();
+    let a = tree.add_root("a");
+    let b = tree.add_root("b");
+    let a1 = tree.add_child(a, "a1").unwrap();
+    let a2 = tree.add_child(a, "a2").unwrap();
+    let b1 = tree.add_child(b, "b1").unwrap();
+    let a1x = tree.add_child(a1, "a1x").unwrap();
+    let b1x = tree.add_child(b1, "b1x").unwrap();
+
+    let order: Vec<u32> = tree.iter_bfs().map(|node| node.borrow().id).collect();
+    assert_eq!(order, vec![a, b, a1, a2, b1, a1x, b1x]);
+
+    let dfs: Vec<u32> = tree.iter_dfs().map(|node| node.borrow().id).collect();
+    assert_eq!(dfs, vec![a, a1, a1x, a2, b, b1, b1x]);
+}
+
+#[test]
+fn clone_is_independent_of_the_original() {
+    let original = small_plan();
+    let mut copy = original.clone();
+    let design = original.find_first(|node| node.title == "design").unwrap();
+
+    copy.rename(design, "redesign").unwrap();
+    copy.add_child(design, "extra").unwrap();
+    copy.toggle(design);
+
+    let design_ref = original.get(design).unwrap();
+    assert_eq!(design_ref.borrow().title, "design");
+    assert_eq!(design_ref.borrow().children.len(), 1);
+    assert!(!Rc::ptr_eq(&design_ref, &copy.get(design).unwrap()));
+    assert_eq!(original.node_count(), 4);
+    assert!(original.validate().is_ok());
+    assert!(copy.validate().is_ok());
+}
+
+#[test]
+fn structurally_eq_ignores_ids_but_not_order() {
+    let tree = small_plan();
+
+    let mut renumbered: Tree = Tree::with_starting_id(50);
+    renumbered.merge(small_plan()).unwrap();
+    assert!(renumbered.structurally_eq(&tree));
+
+    let mut reordered = small_plan();
+    let plan = reordered.child_order(None)[0];
+    reordered.reverse_children(Some(plan));
+    assert!(!reordered.structurally_eq(&tree));
+
+    let mut toggled = small_plan();
+    let build = toggled.find_first(|node| node.title == "build").unwrap();
+    toggled.toggle(build);
+    assert!(!toggled.structurally_eq(&tree));
+
+    let mut retitled = small_plan();
+    retitled.rename(plan, "Plan").unwrap();
+    assert!(!retitled.structurally_eq(&tree));
+}
+
+#[test]
+fn from_builder_materializes_nested_nodes() {
+    let tree: Tree = Tree::from_builder([TreeBuilder::node("plan").children([
+        TreeBuilder::node("design")
+            .done()
+            .child(TreeBuilder::node("sketch").done()),
+        TreeBuilder::node("build"),
+    ])]);
+
+    assert!(tree.structurally_eq(&small_plan()));
+    // Ids are handed out in pre-order
+    let ids: Vec<u32> = tree.iter_dfs().map(|node| node.borrow().id).collect();
+    assert_eq!(ids, vec![1, 2, 3, 4]);
+    assert_eq!(tree.next_id, 5);
+    assert!(tree.validate().is_ok());
+}
+
+#[test]
+fn from_jsonl_accepts_shuffled_lines() {
+    let tree = small_plan();
+    let jsonl = tree.to_jsonl();
+    let lines: Vec<&str> = jsonl.lines().collect();
+    assert_eq!(lines.len(), 4);
+    // plan, design, sketch, build -> sketch, design, build, plan: children
+    // before parents, siblings still in order
+    let shuffled = [lines[2], lines[1], lines[3], lines[0]].join("\n");
+
+    let loaded: Tree = Tree::from_jsonl(shuffled.as_bytes()).unwrap();
+    assert!(loaded.structurally_eq(&tree));
+    assert_eq!(loaded.next_id, tree.next_id);
+}
+
+#[test]
+fn from_jsonl_reports_the_malformed_line() {
+    let input = concat!(
+        "{\"id\":1,\"parent\":null,\"done\":false,\"title\":\"root\"}\n",
+        "\n",
+        "{\"id\":2,\"parent\":1,\"done\":false}\n",
+    );
+    let err = load_err(Tree::from_jsonl(input.as_bytes()));
+    assert_eq!(err, "line 3: missing or invalid \"title\"");
+
+    let err = load_err(Tree::from_jsonl("not json\n".as_bytes()));
+    assert!(err.starts_with("line 1: "), "{}", err);
+
+    let blank = concat!(
+        "{\"id\":1,\"parent\":null,\"done\":false,\"title\":\"root\"}\n",
+        "{\"id\":2,\"parent\":1,\"done\":false,\"title\":\"  \"}\n",
+    );
+    let err = load_err(Tree::from_jsonl(blank.as_bytes()));
+    assert_eq!(err, "line 2: node 2 has an empty title");
+
+    let err = load_err(Tree::from_csv("id,parent_id,title,done\n1,,,false\n"));
+    assert_eq!(err, "line 2: node 1 has an empty title");
+}
@@ -0,0 +1,271 @@
+// src/xml.rs
+
+//! Just enough XML to read OPML: elements, attributes and entities.
+//! Text content, comments, processing instructions and DOCTYPEs are skipped.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attrs: Vec<(String, String)>, // In document order, entities decoded
+    pub children: Vec<Element>,
+}
+
+impl Element {
+    /// Value of the attribute `name`, if present
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The first child element called `name`
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+/// Parse a document and return its root element
+pub fn parse(input: &str) -> Result<Element, String> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+    };
+
+    // Open elements, innermost last; explicit so deep outlines can't overflow
+    let mut open: Vec<Element> = Vec::new();
+    let mut root = None;
+    loop {
+        parser.skip_text();
+        if parser.pos >= parser.chars.len() {
+            break;
+        }
+
+        if parser.eat("<?") {
+            parser.skip_past("?>")?;
+        } else if parser.eat("<!--") {
+            parser.skip_past("-->")?;
+        } else if parser.eat("<!") {
+            parser.skip_past(">")?;
+        } else if parser.eat("</") {
+            let name = parser.parse_name()?;
+            parser.skip_whitespace();
+            parser.expect('>')?;
+
+            let element = open
+                .pop()
+                .ok_or_else(|| format!("unexpected closing tag </{}>", name))?;
+            if element.name != name {
+                return Err(format!(
+                    "closing tag </{}> does not match <{}>",
+                    name, element.name
+                ));
+            }
+            finish(element, &mut open, &mut root)?;
+        } else {
+            parser.expect('<')?;
+            let (element, self_closing) = parser.parse_start_tag()?;
+            if self_closing {
+                finish(element, &mut open, &mut root)?;
+            } else {
+                open.push(element);
+            }
+        }
+    }
+
+    if let Some(element) = open.last() {
+        return Err(format!("unclosed tag <{}>", element.name));
+    }
+    root.ok_or_else(|| "no root element".to_string())
+}
+
+/// Attach a completed element to its parent, or make it the root
+fn finish(
+    element: Element,
+    open: &mut [Element],
+    root: &mut Option<Element>,
+) -> Result<(), String> {
+    match open.last_mut() {
+        Some(parent) => parent.children.push(element),
+        None if root.is_none() => *root = Some(element),
+        None => return Err("more than one root element".into()),
+    }
+
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skip character data up to the next tag
+    fn skip_text(&mut self) {
+        while self.peek().is_some_and(|c| c != '<') {
+            self.pos += 1;
+        }
+    }
+
+    /// Consume `s` if the input continues with it
+    fn eat(&mut self, s: &str) -> bool {
+        let len = s.chars().count();
+        if self.pos + len > self.chars.len() {
+            return false;
+        }
+
+        if self.chars[self.pos..self.pos + len]
+            .iter()
+            .copied()
+            .eq(s.chars())
+        {
+            self.pos += len;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), String> {
+        let start = self.pos;
+        while self.pos < self.chars.len() {
+            if self.eat(end) {
+                return Ok(());
+            }
+            self.pos += 1;
+        }
+
+        Err(format!("missing '{}' for markup at offset {}", end, start))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at offset {}", c, self.pos))
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || "_-.:".contains(c))
+        {
+            self.pos += 1;
+        }
+
+        if self.pos == start {
+            return Err(format!("expected a name at offset {}", start));
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    /// Parse the rest of a start tag after `<`. Returns the element and
+    /// whether it was self-closing (`<outline ... />`).
+    fn parse_start_tag(&mut self) -> Result<(Element, bool), String> {
+        let name = self.parse_name()?;
+
+        let mut attrs = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.eat("/>") {
+                return Ok((
+                    Element {
+                        name,
+                        attrs,
+                        children: Vec::new(),
+                    },
+                    true,
+                ));
+            }
+            if self.eat(">") {
+                return Ok((
+                    Element {
+                        name,
+                        attrs,
+                        children: Vec::new(),
+                    },
+                    false,
+                ));
+            }
+
+            let key = self.parse_name()?;
+            self.skip_whitespace();
+            self.expect('=')?;
+            self.skip_whitespace();
+            let value = self.parse_attr_value()?;
+            attrs.push((key, value));
+        }
+    }
+
+    fn parse_attr_value(&mut self) -> Result<String, String> {
+        let quote = match self.peek() {
+            Some(c @ ('"' | '\'')) => c,
+            _ => return Err(format!("expected a quoted value at offset {}", self.pos)),
+        };
+        self.pos += 1;
+
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != quote) {
+            self.pos += 1;
+        }
+        if self.peek().is_none() {
+            return Err(format!("unterminated attribute value at offset {}", start));
+        }
+
+        let raw: String = self.chars[start..self.pos].iter().collect();
+        self.pos += 1;
+        unescape(&raw)
+    }
+}
+
+/// Decode the predefined entities and numeric character references
+fn unescape(text: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let semi = rest
+            .find(';')
+            .ok_or_else(|| format!("unterminated entity in '{}'", text))?;
+        let entity = &rest[1..semi];
+        let decoded = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| format!("unknown entity '&{};'", entity))?
+            }
+        };
+        out.push(decoded);
+        rest = &rest[semi + 1..];
+    }
+    out.push_str(rest);
+
+    Ok(out)
+}